@@ -1,7 +1,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod systems;
 
-use std::{rc::Rc, vec};
+use std::{rc::Rc, sync::Arc, vec};
 
 use macroquad::{
     prelude::*,
@@ -11,14 +11,36 @@ use macroquad::{
     },
 };
 use systems::{
-    blocks::{Block, BlockType, RenderSides},
-    chunks::Chunk,
-    demo_features::DemoFeatures,
+    atlas::{chunk_material, TextureAtlas, DEFAULT_ATLAS_TILES},
+    blocks::BlockType,
+    caves::CaveSettings,
+    editing::{break_block, place_block},
+    frustum::Frustum,
+    hud::{draw_crosshair, draw_target, side_of},
+    lod::LodSettings,
+    meshing::MeshingMode,
+    ores::default_ores,
+    raycast::raycast,
+    registry::{BlockRegistry, DEFAULT_BLOCKS},
+    render_utils::{MAX_MESH_INDICES, MAX_MESH_VERTICES},
+    streaming::{ChunkStreamer, StreamSettings},
+    structures::{StructureBlocks, StructurePlacer},
+    terrain::{TerrainBlocks, TerrainGenerator},
+    timestep::FixedTimestep,
+    workers::{default_threads, ChunkWorkers, Finished},
+    world::{world_to_chunk, World},
 };
 
 use crate::systems::controls::*;
 
 const CHUNK_SIZE_16: i32 = 16;
+const DEFAULT_SEED: u64 = 1337;
+/// Dirty chunks handed to the workers for meshing each frame.
+const MESHES_PER_FRAME: usize = 8;
+/// Finished chunks and meshes inserted or uploaded each frame.
+const RESULTS_PER_FRAME: usize = 8;
+/// How far away, in blocks, the player can pick blocks.
+const REACH: f32 = 6.0;
 fn conf() -> Conf {
     Conf {
         window_title: String::from("Minequad"),
@@ -34,10 +56,14 @@ fn conf() -> Conf {
 async fn load_tex() -> Vec<Rc<Texture2D>> {
     let mut textures: Vec<Rc<Texture2D>> = vec![];
 
-    let stone = load_texture("assets/textures/stone.png").await.unwrap();
+    let stone = load_texture("assets/textures/blocks/stone.png")
+        .await
+        .unwrap();
     stone.set_filter(FilterMode::Nearest);
 
-    let grass = load_texture("assets/textures/grass.png").await.unwrap();
+    let grass = load_texture("assets/textures/blocks/grass.png")
+        .await
+        .unwrap();
     grass.set_filter(FilterMode::Nearest);
 
     textures.push(stone.into());
@@ -46,31 +72,100 @@ async fn load_tex() -> Vec<Rc<Texture2D>> {
     textures
 }
 
-static STONE: &[u8] = include_bytes!("../assets/textures/stone.png");
-static GRASS: &[u8] = include_bytes!("../assets/textures/grass.png");
+static STONE: &[u8] = include_bytes!("../assets/textures/blocks/stone.png");
+static GRASS: &[u8] = include_bytes!("../assets/textures/blocks/grass.png");
+static ATLAS: &[u8] = include_bytes!("../assets/textures/atlas.png");
+
+/// Uses the shipped atlas, or stitches one from the PNGs in `MINEQUAD_TEXTURE_DIR`
+/// when that is set, e.g. to `assets/textures/blocks`.
+fn load_atlas() -> TextureAtlas {
+    match std::env::var("MINEQUAD_TEXTURE_DIR") {
+        Ok(dir) => TextureAtlas::from_dir(dir),
+        Err(_) => Ok(TextureAtlas::from_strip(
+            Image::from_file_with_format(ATLAS, Some(ImageFormat::Png)).unwrap(),
+            &DEFAULT_ATLAS_TILES,
+        )),
+    }
+    .unwrap_or_else(|err| panic!("cannot build the texture atlas: {err}"))
+}
+
+/// Seed from `MINEQUAD_SEED`, or a fixed default so every run starts in the same
+/// world.
+fn world_seed() -> u64 {
+    std::env::var("MINEQUAD_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(DEFAULT_SEED)
+}
+
+/// Uses the shipped `assets/blocks.toml`, or the definitions file named by
+/// `MINEQUAD_BLOCKS` when that is set.
+fn load_blocks(atlas: &TextureAtlas) -> BlockRegistry {
+    match std::env::var("MINEQUAD_BLOCKS") {
+        Ok(path) => BlockRegistry::load(path, &atlas.layout),
+        Err(_) => BlockRegistry::from_toml(DEFAULT_BLOCKS, &atlas.layout),
+    }
+    .unwrap_or_else(|err| panic!("cannot load the block definitions: {err}"))
+}
 
 #[macroquad::main(conf)]
 async fn main() {
+    gl_set_drawcall_buffer_capacity(MAX_MESH_VERTICES + 1, MAX_MESH_INDICES + 1);
     // let textures = load_tex().await;
     let stone_tex = Texture2D::from_file_with_format(STONE, Some(ImageFormat::Png));
     stone_tex.set_filter(FilterMode::Nearest);
     let grass_tex = Texture2D::from_file_with_format(GRASS, Some(ImageFormat::Png));
     grass_tex.set_filter(FilterMode::Nearest);
+    let atlas = load_atlas();
+    let atlas_tex = atlas.upload();
+    let chunk_material = chunk_material(&atlas.layout);
+    let blocks = Arc::new(load_blocks(&atlas));
+    let terrain_blocks = TerrainBlocks::from_registry(&blocks)
+        .unwrap_or_else(|err| panic!("cannot set up terrain generation: {err}"));
+    let generator = Arc::new(
+        TerrainGenerator::new(world_seed(), terrain_blocks)
+            .with_biomes()
+            .with_caves(CaveSettings::default())
+            .with_ores(
+                default_ores(&blocks)
+                    .unwrap_or_else(|err| panic!("cannot set up ore generation: {err}")),
+            ),
+    );
+    let structure_blocks = StructureBlocks::from_registry(&blocks)
+        .unwrap_or_else(|err| panic!("cannot set up structure placement: {err}"));
+    let mut structures = StructurePlacer::new();
+    let mut workers = ChunkWorkers::new(
+        default_threads(),
+        Arc::clone(&generator),
+        structure_blocks,
+        Arc::clone(&blocks),
+    );
 
-    // let mut demo = DemoFeatures::new(&stone_tex);
     let mut player = Player::new();
+    player.teleport(vec3(0.5, generator.height(0, 0) as f32 + 3.0, 0.5));
+    let mut timestep = FixedTimestep::default();
     let mut projection = 0;
+    let mut meshing = 0;
+    let placeable: Vec<BlockType> = blocks
+        .iter()
+        .filter(|block| block.faces.is_some())
+        .map(|block| block.id)
+        .collect();
+    let placeable_names: Vec<&str> = placeable
+        .iter()
+        .map(|&block| blocks.get(block).name.as_str())
+        .collect();
+    let mut selected = 0;
     let LIGHTBLUE = Color {
         r: 135.0 / 255.0,
         g: 206.0 / 255.0,
         b: 250.0 / 255.0,
         a: 1.0,
     };
-    let mut chunk = Chunk::new((1.0, 0.0, 0.0));
-    chunk.from_fn(&stone_tex, |x, y, z| {
-        ((x as f32).cos() + (y as f32).tan() * (z as f32).sin()).sin() > 0.0
-    });
-    chunk.connected_blocks();
+    let mut world = World::new();
+    let mut streamer = ChunkStreamer::new(StreamSettings::default());
+    let mut render_distance = streamer.settings().render_distance as f32;
+    let mut lod_distance = LodSettings::default().distances[0] as f32;
 
     loop {
         clear_background(LIGHTBLUE);
@@ -79,7 +174,81 @@ async fn main() {
         } else if projection == 1 {
             player.projection = Projection::Orthographics;
         }
-        player.update();
+        if meshing == 0 {
+            world.set_meshing_mode(MeshingMode::Naive);
+        } else if meshing == 1 {
+            world.set_meshing_mode(MeshingMode::Greedy);
+        }
+        // Blocks in chunks that have not arrived yet count as solid, so the
+        // player waits for the ground instead of falling through it.
+        player.update(&mut timestep, |pos| {
+            let (coord, local) = world_to_chunk(pos);
+            world
+                .chunk(coord)
+                .is_none_or(|chunk| blocks.is_solid(chunk.get(local.x, local.y, local.z)))
+        });
+        let frustum = Frustum::from_camera(&player.camera());
+        let pick = |world: &World| {
+            raycast(player.eye(), player.front(), REACH, |pos| {
+                blocks.is_drawn(world.get_block(pos))
+            })
+        };
+        let mut target = pick(&world);
+        if let (Some(action), Some(hit)) = (player.block_action(), target) {
+            match action {
+                BlockAction::Break => {
+                    break_block(&mut world, &blocks, &hit);
+                }
+                BlockAction::Place => {
+                    // Refused placements just leave the world as it is.
+                    let _ = place_block(
+                        &mut world,
+                        &blocks,
+                        &hit,
+                        placeable[selected],
+                        &player.bounds(),
+                    );
+                }
+            }
+            target = pick(&world);
+        }
+        streamer.set_settings(StreamSettings {
+            render_distance: render_distance.round() as i32,
+            ..streamer.settings()
+        });
+        for coord in streamer.update(player.position, &mut world) {
+            structures.unload(&world, coord);
+        }
+        streamer.load(&mut world, |_, coord| workers.generate(coord));
+        let (player_chunk, _) = world_to_chunk(player.position.floor().as_ivec3());
+        world.update_lod(
+            player_chunk,
+            &LodSettings::spaced(lod_distance.round() as i32),
+        );
+        for input in world.take_dirty(MESHES_PER_FRAME, player_chunk, &blocks) {
+            workers.mesh(input, world.meshing_mode());
+        }
+        for _ in 0..RESULTS_PER_FRAME {
+            let Some(finished) = workers.poll() else {
+                break;
+            };
+            match finished {
+                Finished::Generated { chunk, writes } => {
+                    let coord = chunk.position();
+                    if streamer.is_wanted(coord) && world.chunk(coord).is_none() {
+                        structures.place(&mut world, chunk, writes);
+                    }
+                }
+                Finished::Meshed {
+                    coord,
+                    meshes,
+                    connections,
+                } => {
+                    world.set_meshes(coord, meshes, &atlas_tex);
+                    world.set_connections(coord, connections);
+                }
+            }
+        }
 
         draw_grid(100, 1., BLACK, GRAY);
 
@@ -92,8 +261,12 @@ async fn main() {
             WHITE,
         );
 
-        chunk.render();
-        // demo.render();
+        gl_use_material(&chunk_material);
+        let render_stats = world.render(&frustum, player.eye());
+        gl_use_default_material();
+        if let Some(hit) = target {
+            draw_target(&hit);
+        }
 
         ui::root_ui().group(
             hash!(),
@@ -106,19 +279,67 @@ async fn main() {
                     &["Perspective", "Orthographic"],
                     &mut projection,
                 );
+                ui.combo_box(hash!(), "Meshing", &["Naive", "Greedy"], &mut meshing);
+                ui.combo_box(hash!(), "Block", &placeable_names, &mut selected);
+                ui.slider(hash!(), "Render distance", 2.0..16.0, &mut render_distance);
+                ui.slider(hash!(), "LOD distance", 1.0..16.0, &mut lod_distance);
                 ui.label(None, format!("FPS: {}", get_fps()).as_str());
-                        ui.label(
-                            None,
-                            format!(
-                                "X: {:.2} Y: {:.2} Z: {:.2}",
-                                player.position.x, player.position.y, player.position.z
-                            )
-                            .as_str(),
-                        );
-                        ui.label(
-                            None,
-                            format!("Yaw: {:.2} Pitch: {:.2}", player.yaw, player.pitch).as_str(),
-                        );
+                ui.label(
+                    None,
+                    format!(
+                        "Chunks: {} Queued: {}",
+                        world.chunk_count(),
+                        streamer.queued()
+                    )
+                    .as_str(),
+                );
+                ui.label(None, format!("Jobs: {}", workers.in_flight()).as_str());
+                ui.label(
+                    None,
+                    format!(
+                        "Drawn: {} Culled: {} Occluded: {}",
+                        render_stats.drawn, render_stats.culled, render_stats.occluded
+                    )
+                    .as_str(),
+                );
+                ui.label(
+                    None,
+                    format!(
+                        "X: {:.2} Y: {:.2} Z: {:.2}",
+                        player.position.x, player.position.y, player.position.z
+                    )
+                    .as_str(),
+                );
+                let column = player.position.floor().as_ivec3();
+                if let Some(biome) = generator.biome(column.x, column.z) {
+                    ui.label(None, format!("Biome: {:?}", biome).as_str());
+                }
+                if let Some(hit) = target {
+                    let block = world.get_block(hit.block);
+                    ui.label(
+                        None,
+                        format!(
+                            "Target: {} {} {} {} {} Light: {}",
+                            hit.block.x,
+                            hit.block.y,
+                            hit.block.z,
+                            blocks.get(block).name,
+                            side_of(hit.normal)
+                                .map(|side| format!("{:?}", side))
+                                .unwrap_or_default(),
+                            blocks.light_emission(block)
+                        )
+                        .as_str(),
+                    );
+                }
+                ui.label(
+                    None,
+                    format!("Yaw: {:.2} Pitch: {:.2}", player.yaw, player.pitch).as_str(),
+                );
+                ui.label(
+                    None,
+                    format!("Movement: {:?} (F)", player.movement).as_str(),
+                );
             },
         );
 
@@ -126,6 +347,7 @@ async fn main() {
             break;
         }
         set_default_camera();
+        draw_crosshair();
         next_frame().await
     }
 }
@@ -0,0 +1,25 @@
+use macroquad::prelude::*;
+
+/// Axis aligned box between two corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// The unit cube the block at `pos` fills.
+    pub fn block(pos: IVec3) -> Self {
+        let min = pos.as_vec3();
+        Self::new(min, min + Vec3::ONE)
+    }
+
+    /// Whether the boxes share any volume. Boxes that only touch do not.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && other.min.cmplt(self.max).all()
+    }
+}
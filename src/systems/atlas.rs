@@ -0,0 +1,359 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, path::PathBuf};
+
+use macroquad::prelude::*;
+
+/// Tile names of the shipped `assets/textures/atlas.png`, top to bottom.
+pub const DEFAULT_ATLAS_TILES: [&str; 14] = [
+    "grass",
+    "stone",
+    "dirt",
+    "grass_side",
+    "sand",
+    "snow",
+    "snow_side",
+    "coal_ore",
+    "iron_ore",
+    "gold_ore",
+    "log",
+    "log_top",
+    "leaves",
+    "cobblestone",
+];
+
+/// One tile of the atlas. `index` counts tiles row by row and is passed to the
+/// chunk shader so tiled UVs can wrap inside the tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasTile {
+    pub index: u32,
+    pub rect: Rect,
+}
+
+impl AtlasTile {
+    /// Maps a texture coordinate local to the tile, where 0..1 spans the tile once,
+    /// into atlas space.
+    pub fn uv(&self, local: Vec2) -> Vec2 {
+        vec2(self.rect.x, self.rect.y) + local * vec2(self.rect.w, self.rect.h)
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(PathBuf, io::Error),
+    Decode(PathBuf, String),
+    Empty(PathBuf),
+    SizeMismatch {
+        path: PathBuf,
+        expected: (u16, u16),
+        found: (u16, u16),
+    },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            AtlasError::Decode(path, err) => {
+                write!(f, "cannot decode {}: {}", path.display(), err)
+            }
+            AtlasError::Empty(path) => write!(f, "no .png textures in {}", path.display()),
+            AtlasError::SizeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} is {}x{}, but atlas tiles are {}x{}",
+                path.display(),
+                found.0,
+                found.1,
+                expected.0,
+                expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// Where every named tile sits in the atlas image.
+#[derive(Clone, Debug)]
+pub struct AtlasLayout {
+    columns: u32,
+    rows: u32,
+    names: HashMap<String, u32>,
+}
+
+impl AtlasLayout {
+    /// Lays `names` out row by row on a `columns` x `rows` grid.
+    pub fn new(columns: u32, rows: u32, names: &[&str]) -> Self {
+        assert!(names.len() as u32 <= columns * rows);
+        Self {
+            columns,
+            rows,
+            names: names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| (name.to_string(), index as u32))
+                .collect(),
+        }
+    }
+
+    /// Number of tile columns and rows, as the chunk shader expects it.
+    pub fn grid(&self) -> Vec2 {
+        vec2(self.columns as f32, self.rows as f32)
+    }
+
+    pub fn tile(&self, name: &str) -> Option<AtlasTile> {
+        let index = *self.names.get(name)?;
+        let size = vec2(1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        Some(AtlasTile {
+            index,
+            rect: Rect::new(
+                (index % self.columns) as f32 * size.x,
+                (index / self.columns) as f32 * size.y,
+                size.x,
+                size.y,
+            ),
+        })
+    }
+}
+
+/// CPU side atlas: the stitched image and its layout.
+pub struct TextureAtlas {
+    pub image: Image,
+    pub layout: AtlasLayout,
+}
+
+impl TextureAtlas {
+    /// Uses an already stitched atlas image whose tiles are square and stacked in
+    /// a single column, like the shipped `atlas.png`.
+    pub fn from_strip(image: Image, names: &[&str]) -> Self {
+        let rows = (image.height / image.width) as u32;
+        Self {
+            layout: AtlasLayout::new(1, rows, names),
+            image,
+        }
+    }
+
+    /// Stitches every `.png` in `dir` into one atlas, named after the file stems.
+    /// All textures must have the same size.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, AtlasError> {
+        let dir = dir.as_ref();
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|err| AtlasError::Io(dir.to_path_buf(), err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+            .collect();
+        paths.sort();
+
+        let mut tiles = Vec::with_capacity(paths.len());
+        for path in paths {
+            let bytes = fs::read(&path).map_err(|err| AtlasError::Io(path.clone(), err))?;
+            let image = Image::from_file_with_format(&bytes, Some(ImageFormat::Png))
+                .map_err(|err| AtlasError::Decode(path.clone(), err.to_string()))?;
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            tiles.push((name, path, image));
+        }
+        Self::stitch(dir, tiles)
+    }
+
+    fn stitch(dir: &Path, tiles: Vec<(String, PathBuf, Image)>) -> Result<Self, AtlasError> {
+        let Some((_, _, first)) = tiles.first() else {
+            return Err(AtlasError::Empty(dir.to_path_buf()));
+        };
+        let (width, height) = (first.width, first.height);
+        for (_, path, image) in &tiles {
+            if (image.width, image.height) != (width, height) {
+                return Err(AtlasError::SizeMismatch {
+                    path: path.clone(),
+                    expected: (width, height),
+                    found: (image.width, image.height),
+                });
+            }
+        }
+
+        let columns = (tiles.len() as f32).sqrt().ceil() as u32;
+        let rows = (tiles.len() as u32).div_ceil(columns);
+        let mut image = Image::gen_image_color(
+            width * columns as u16,
+            height * rows as u16,
+            Color::new(0.0, 0.0, 0.0, 0.0),
+        );
+        for (index, (_, _, tile)) in tiles.iter().enumerate() {
+            let x0 = (index as u32 % columns) * width as u32;
+            let y0 = (index as u32 / columns) * height as u32;
+            for y in 0..height as u32 {
+                for x in 0..width as u32 {
+                    image.set_pixel(x0 + x, y0 + y, tile.get_pixel(x, y));
+                }
+            }
+        }
+
+        let names: Vec<&str> = tiles.iter().map(|(name, _, _)| name.as_str()).collect();
+        Ok(Self {
+            layout: AtlasLayout::new(columns, rows, &names),
+            image,
+        })
+    }
+
+    pub fn upload(&self) -> Texture2D {
+        let texture = Texture2D::from_image(&self.image);
+        texture.set_filter(FilterMode::Nearest);
+        texture
+    }
+}
+
+const CHUNK_VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+attribute vec4 normal;
+
+varying lowp vec4 color;
+varying highp vec2 uv;
+varying highp vec2 tile_origin;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+uniform vec2 AtlasGrid;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+    tile_origin = vec2(mod(normal.w, AtlasGrid.x), floor(normal.w / AtlasGrid.x)) / AtlasGrid;
+}
+"#;
+
+const CHUNK_FRAGMENT_SHADER: &str = r#"#version 100
+precision highp float;
+
+varying lowp vec4 color;
+varying highp vec2 uv;
+varying highp vec2 tile_origin;
+
+uniform sampler2D Texture;
+uniform vec2 AtlasGrid;
+
+void main() {
+    // Greedy quads span several blocks; repeat the tile instead of running into
+    // its neighbours in the atlas.
+    vec2 local = fract((uv - tile_origin) * AtlasGrid);
+    gl_FragColor = color * texture2D(Texture, tile_origin + local / AtlasGrid);
+}
+"#;
+
+/// Material for chunk meshes that wraps texture coordinates inside their atlas
+/// tile, which the tile index in `normal.w` identifies.
+pub fn chunk_material(layout: &AtlasLayout) -> Material {
+    let material = load_material(
+        ShaderSource::Glsl {
+            vertex: CHUNK_VERTEX_SHADER,
+            fragment: CHUNK_FRAGMENT_SHADER,
+        },
+        MaterialParams {
+            pipeline_params: PipelineParams {
+                depth_write: true,
+                depth_test: Comparison::LessOrEqual,
+                ..Default::default()
+            },
+            uniforms: vec![UniformDesc::new("AtlasGrid", UniformType::Float2)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    material.set_uniform("AtlasGrid", layout.grid());
+    material
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_image(color: Color) -> Image {
+        Image::gen_image_color(2, 2, color)
+    }
+
+    #[test]
+    fn strip_layout_matches_shipped_atlas() {
+        let atlas =
+            TextureAtlas::from_strip(Image::gen_image_color(16, 224, WHITE), &DEFAULT_ATLAS_TILES);
+        let grass = atlas.layout.tile("grass").unwrap();
+        let dirt = atlas.layout.tile("dirt").unwrap();
+        let size = 1.0 / 14.0;
+        assert_eq!(grass.rect, Rect::new(0.0, 0.0, 1.0, size));
+        assert_eq!(dirt.rect, Rect::new(0.0, 2.0 * size, 1.0, size));
+        assert_eq!(dirt.index, 2);
+        assert_eq!(atlas.layout.grid(), vec2(1.0, 14.0));
+    }
+
+    #[test]
+    fn tile_uv_maps_local_coordinates() {
+        let layout = AtlasLayout::new(2, 2, &["dirt", "grass_side", "stone", "grass"]);
+        let tile = layout.tile("stone").unwrap();
+        assert_eq!(tile.index, 2);
+        assert_eq!(tile.uv(vec2(0.0, 0.0)), vec2(0.0, 0.5));
+        assert_eq!(tile.uv(vec2(1.0, 1.0)), vec2(0.5, 1.0));
+        assert_eq!(tile.uv(vec2(3.0, 1.0)), vec2(1.5, 1.0));
+    }
+
+    #[test]
+    fn stitch_places_tiles_on_a_grid() {
+        let colors = [
+            Color::new(1.0, 0.0, 0.0, 1.0),
+            Color::new(0.0, 1.0, 0.0, 1.0),
+            Color::new(0.0, 0.0, 1.0, 1.0),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        ];
+        let tiles = ["dirt", "grass", "grass_side", "stone"]
+            .iter()
+            .zip(colors)
+            .map(|(name, color)| (name.to_string(), PathBuf::from(name), tile_image(color)))
+            .collect();
+        let atlas = TextureAtlas::stitch(Path::new("test"), tiles).unwrap();
+        assert_eq!((atlas.image.width, atlas.image.height), (4, 4));
+        assert_eq!(atlas.layout.grid(), vec2(2.0, 2.0));
+        assert_eq!(atlas.image.get_pixel(0, 0), colors[0]);
+        assert_eq!(atlas.image.get_pixel(3, 1), colors[1]);
+        assert_eq!(atlas.image.get_pixel(1, 3), colors[2]);
+        assert_eq!(atlas.image.get_pixel(2, 2), colors[3]);
+        assert_eq!(
+            atlas.layout.tile("grass_side").unwrap().rect,
+            Rect::new(0.0, 0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn stitch_rejects_mismatched_sizes() {
+        let tiles = vec![
+            (
+                "grass".to_string(),
+                PathBuf::from("grass.png"),
+                tile_image(RED),
+            ),
+            (
+                "stone".to_string(),
+                PathBuf::from("stone.png"),
+                Image::gen_image_color(4, 4, RED),
+            ),
+        ];
+        let err = TextureAtlas::stitch(Path::new("test"), tiles)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            AtlasError::SizeMismatch { found: (4, 4), .. }
+        ));
+        assert!(matches!(
+            TextureAtlas::stitch(Path::new("test"), vec![]),
+            Err(AtlasError::Empty(_))
+        ));
+    }
+
+    #[test]
+    fn builds_from_the_shipped_block_textures() {
+        let atlas = TextureAtlas::from_dir("assets/textures/blocks").unwrap();
+        assert!(atlas.layout.tile("grass").is_some());
+        assert!(atlas.layout.tile("stone").is_some());
+    }
+}
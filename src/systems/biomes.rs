@@ -0,0 +1,244 @@
+use crate::systems::noise::{splitmix64, Fbm, GradientNoise};
+
+/// Mixed into the world seed so the climate maps differ from the heightmap and
+/// cave noise of the same seed.
+const CLIMATE_SALT: u64 = 0x0063_6C69_6D61_7465;
+
+const CLIMATE: Fbm = Fbm {
+    octaves: 3,
+    frequency: 1.0 / 512.0,
+    lacunarity: 2.0,
+    gain: 0.5,
+};
+
+/// Half the width, in climate units, of the band around each threshold where
+/// neighbouring biomes blend into each other.
+const BLEND: f64 = 0.12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+/// What a biome puts in its columns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiomeSettings {
+    /// Block name on top of every column.
+    pub surface: &'static str,
+    /// Block name of the layer between the surface block and the stone.
+    pub filler: &'static str,
+    /// Added to the base height of the terrain.
+    pub height_offset: f64,
+    /// Largest distance the surface strays from its offset base height.
+    pub height_scale: f64,
+    /// Chance that a surface column carries a decoration such as a tree.
+    pub decoration_density: f64,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 4] = [
+        Biome::Plains,
+        Biome::Desert,
+        Biome::Tundra,
+        Biome::Mountains,
+    ];
+
+    pub fn settings(self) -> BiomeSettings {
+        match self {
+            Biome::Plains => BiomeSettings {
+                surface: "grass",
+                filler: "dirt",
+                height_offset: 0.0,
+                height_scale: 10.0,
+                decoration_density: 0.01,
+            },
+            Biome::Desert => BiomeSettings {
+                surface: "sand",
+                filler: "sand",
+                height_offset: -3.0,
+                height_scale: 6.0,
+                decoration_density: 0.002,
+            },
+            Biome::Tundra => BiomeSettings {
+                surface: "snow",
+                filler: "dirt",
+                height_offset: 2.0,
+                height_scale: 12.0,
+                decoration_density: 0.004,
+            },
+            Biome::Mountains => BiomeSettings {
+                surface: "stone",
+                filler: "stone",
+                height_offset: 16.0,
+                height_scale: 36.0,
+                decoration_density: 0.003,
+            },
+        }
+    }
+}
+
+/// Temperature and humidity at one column, both in roughly -1..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Climate {
+    pub temperature: f64,
+    pub humidity: f64,
+}
+
+/// 0 below `edge - BLEND`, 1 above `edge + BLEND` and a smooth ramp between.
+fn above(value: f64, edge: f64) -> f64 {
+    let t = ((value - edge + BLEND) / (2.0 * BLEND)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Climate {
+    /// How strongly each biome of `Biome::ALL` applies here. The weights are
+    /// continuous in the climate and always sum to one: cold land is tundra, hot
+    /// and dry land desert, and of the rest the humid part mountains and the
+    /// remainder plains.
+    pub fn weights(self) -> [f64; 4] {
+        let cold = 1.0 - above(self.temperature, -0.15);
+        let desert = above(self.temperature, 0.1) * (1.0 - above(self.humidity, 0.05));
+        let wet = above(self.humidity, 0.12);
+        let temperate = (1.0 - cold) * (1.0 - desert);
+        [
+            temperate * (1.0 - wet),
+            (1.0 - cold) * desert,
+            cold,
+            temperate * wet,
+        ]
+    }
+
+    /// The biome with the largest weight.
+    pub fn biome(self) -> Biome {
+        let weights = self.weights();
+        let best = (0..weights.len())
+            .max_by(|&a, &b| weights[a].total_cmp(&weights[b]))
+            .unwrap();
+        Biome::ALL[best]
+    }
+}
+
+/// Height parameters of a column, blended across the biomes that apply to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainShape {
+    pub height_offset: f64,
+    pub height_scale: f64,
+}
+
+/// Seeded temperature and humidity maps, and the biomes they select.
+#[derive(Clone, Debug)]
+pub struct BiomeMap {
+    temperature: GradientNoise,
+    humidity: GradientNoise,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        let mut state = seed ^ CLIMATE_SALT;
+        Self {
+            temperature: GradientNoise::new(splitmix64(&mut state)),
+            humidity: GradientNoise::new(splitmix64(&mut state)),
+        }
+    }
+
+    pub fn climate(&self, x: i32, z: i32) -> Climate {
+        let (x, z) = (x as f64, z as f64);
+        Climate {
+            temperature: CLIMATE.sample(&self.temperature, x, z),
+            humidity: CLIMATE.sample(&self.humidity, x, z),
+        }
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        self.climate(x, z).biome()
+    }
+
+    /// Height parameters of the column at (x, z). Near a border they are a mix of
+    /// both biomes, so the ground rises into mountains instead of stepping up.
+    pub fn shape(&self, x: i32, z: i32) -> TerrainShape {
+        let weights = self.climate(x, z).weights();
+        let mut shape = TerrainShape {
+            height_offset: 0.0,
+            height_scale: 0.0,
+        };
+        for (biome, weight) in Biome::ALL.into_iter().zip(weights) {
+            let settings = biome.settings();
+            shape.height_offset += weight * settings.height_offset;
+            shape.height_scale += weight * settings.height_scale;
+        }
+        shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_sum_to_one_and_pick_the_biome() {
+        let climate = |temperature, humidity| Climate {
+            temperature,
+            humidity,
+        };
+        assert_eq!(climate(0.0, 0.0).biome(), Biome::Plains);
+        assert_eq!(climate(0.5, -0.4).biome(), Biome::Desert);
+        assert_eq!(climate(-0.5, 0.7).biome(), Biome::Tundra);
+        assert_eq!(climate(0.0, 0.5).biome(), Biome::Mountains);
+        assert_eq!(climate(0.5, 0.5).biome(), Biome::Mountains);
+        for i in 0..400 {
+            let climate = climate(i as f64 / 200.0 - 1.0, (i * 7 % 400) as f64 / 200.0 - 1.0);
+            let weights = climate.weights();
+            assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(weights.iter().all(|weight| (0.0..=1.0).contains(weight)));
+        }
+    }
+
+    #[test]
+    fn every_biome_shows_up() {
+        let map = BiomeMap::new(3);
+        let mut counts = [0; 4];
+        for x in (-4096..4096).step_by(32) {
+            for z in (-4096..4096).step_by(32) {
+                counts[map.biome(x, z) as usize] += 1;
+            }
+        }
+        let total: u32 = counts.iter().sum();
+        for (biome, count) in Biome::ALL.into_iter().zip(counts) {
+            assert!(count * 20 > total, "{biome:?}: {count} of {total}");
+        }
+    }
+
+    #[test]
+    fn heights_blend_across_borders() {
+        let map = BiomeMap::new(11);
+        let mut borders = 0;
+        for row in 0..8 {
+            let z = row * 331 - 1200;
+            for x in -3000..3000 {
+                let (a, b) = (map.shape(x, z), map.shape(x + 1, z));
+                // The base height never jumps, even where the biome changes. A
+                // hard border between desert and mountains would step by 19.
+                assert!(
+                    (a.height_offset - b.height_offset).abs() < 1.0,
+                    "({x}, {z})"
+                );
+                assert!((a.height_scale - b.height_scale).abs() < 1.5, "({x}, {z})");
+                borders += (map.biome(x, z) != map.biome(x + 1, z)) as u32;
+            }
+        }
+        assert!(borders > 10, "{borders}");
+    }
+
+    #[test]
+    fn seeds_are_deterministic_and_distinct() {
+        let (a, b, c) = (BiomeMap::new(5), BiomeMap::new(5), BiomeMap::new(6));
+        let columns = (0..200).map(|i| (i * 97 - 9000, i * -61 + 4000));
+        assert!(columns
+            .clone()
+            .all(|(x, z)| a.climate(x, z) == b.climate(x, z)));
+        assert!(columns.clone().any(|(x, z)| a.biome(x, z) != c.biome(x, z)));
+    }
+}
@@ -0,0 +1,142 @@
+use std::ops::RangeInclusive;
+
+use crate::systems::noise::{splitmix64, GradientNoise};
+use macroquad::prelude::*;
+
+/// Mixed into the world seed so the cave noise differs from the heightmap and
+/// climate noise of the same seed.
+const CAVE_SALT: u64 = 0x0063_6176_6573;
+
+/// Average slope of `GradientNoise::sample3` where it crosses zero, used to turn
+/// a tunnel radius in blocks into a noise threshold.
+const NOISE_SLOPE: f64 = 1.17;
+
+/// How the cave pass carves tunnels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaveSettings {
+    /// Roughly how many tunnels cross a 64 block stretch of rock.
+    pub density: f64,
+    /// Tunnel radius in blocks.
+    pub tunnel_radius: f64,
+    /// Depths below the surface, in blocks, where tunnels may be carved. The
+    /// crust above the shallowest depth is never touched.
+    pub depth: RangeInclusive<i32>,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            density: 2.0,
+            tunnel_radius: 1.8,
+            depth: 6..=128,
+        }
+    }
+}
+
+/// Carves winding tunnels along the curves where two independent 3D noise
+/// fields are both zero.
+///
+/// Whether a block is carved depends only on its world position and the
+/// surface height of its column, so chunks can be carved in any order and their
+/// tunnels still line up across borders.
+#[derive(Clone, Debug)]
+pub struct CaveCarver {
+    first: GradientNoise,
+    second: GradientNoise,
+    settings: CaveSettings,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u64, settings: CaveSettings) -> Self {
+        let mut state = seed ^ CAVE_SALT;
+        Self {
+            first: GradientNoise::new(splitmix64(&mut state)),
+            second: GradientNoise::new(splitmix64(&mut state)),
+            settings,
+        }
+    }
+
+    /// Whether the block at world `pos`, in a column whose topmost solid block
+    /// is at `surface`, is hollowed out.
+    pub fn carves(&self, pos: IVec3, surface: i32) -> bool {
+        if !self.settings.depth.contains(&(surface - pos.y)) {
+            return false;
+        }
+        let frequency = self.settings.density / 64.0;
+        let sample = (pos.as_dvec3() + 0.5) * frequency;
+        let first = self.first.sample3(sample.x, sample.y, sample.z);
+        let second = self.second.sample3(sample.x, sample.y, sample.z);
+        let threshold = self.settings.tunnel_radius * NOISE_SLOPE * frequency;
+        first * first + second * second < threshold * threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Share of the blocks in a 96 x 48 x 96 region deep underground that are
+    /// carved.
+    fn carved_share(settings: CaveSettings) -> f64 {
+        let carver = CaveCarver::new(9, settings);
+        let mut carved = 0;
+        for x in 0..96 {
+            for y in 0..48 {
+                for z in 0..96 {
+                    carved += carver.carves(ivec3(x, y, z), 100) as u32;
+                }
+            }
+        }
+        carved as f64 / (96.0 * 48.0 * 96.0)
+    }
+
+    #[test]
+    fn crust_and_bedrock_stay_solid() {
+        let settings = CaveSettings {
+            density: 6.0,
+            tunnel_radius: 6.0,
+            depth: 4..=20,
+        };
+        let carver = CaveCarver::new(1, settings);
+        for x in -40..40 {
+            for z in -40..40 {
+                for depth in (0..4).chain(21..30) {
+                    assert!(!carver.carves(ivec3(x, 50 - depth, z), 50));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tunnels_take_a_modest_share_of_the_rock() {
+        let share = carved_share(CaveSettings::default());
+        assert!((0.005..0.1).contains(&share), "{share}");
+    }
+
+    #[test]
+    fn radius_and_density_grow_the_caves() {
+        let base = carved_share(CaveSettings::default());
+        let wide = carved_share(CaveSettings {
+            tunnel_radius: 3.6,
+            ..CaveSettings::default()
+        });
+        let dense = carved_share(CaveSettings {
+            density: 4.0,
+            ..CaveSettings::default()
+        });
+        // Tunnel cross sections grow with the square of the radius.
+        assert!(wide > base * 3.0, "{wide} vs {base}");
+        assert!(dense > base * 1.5, "{dense} vs {base}");
+    }
+
+    #[test]
+    fn seeds_carve_different_tunnels() {
+        let a = CaveCarver::new(1, CaveSettings::default());
+        let b = CaveCarver::new(2, CaveSettings::default());
+        let differs = (0..4000).any(|i| {
+            let pos = ivec3(i % 40, i / 40 % 10, i / 400);
+            a.carves(pos, 60) != b.carves(pos, 60)
+        });
+        assert!(differs);
+    }
+}
@@ -1,123 +1,301 @@
-use std::ops::BitAnd;
-
-use crate::{systems::blocks::*, CHUNK_SIZE_16};
+use crate::{
+    systems::{
+        blocks::*, palette::PalettedStorage, registry::BlockRegistry, visibility::FaceConnections,
+    },
+    CHUNK_SIZE_16,
+};
 use macroquad::prelude::*;
 
+const CHUNK_VOLUME: usize = (CHUNK_SIZE_16 * CHUNK_SIZE_16 * CHUNK_SIZE_16) as usize;
+
 pub struct Chunk {
-    blocks: Vec<Vec<Vec<Option<Block>>>>,
-    position: (f32, f32, f32),
+    blocks: PalettedStorage<BlockType>,
+    position: IVec3,
+    meshes: Vec<Mesh>,
+    connections: FaceConnections,
+    lod: u8,
+    dirty: bool,
 }
 
-fn proper_mod(a: i32, b: i32) -> i32 {
+pub fn proper_mod(a: i32, b: i32) -> i32 {
     (a % b + b) % b
 }
 
-impl Chunk {
-    pub fn new(position: (f32, f32, f32)) -> Self {
-        // debug_assert!(position.0.floor() == position.0.ceil());
-        // debug_assert!(position.1.floor() == position.1.ceil());
-        // debug_assert!(position.2.floor() == position.2.ceil());
+/// World space corners of the box the chunk at `coord` fills, lowest first.
+pub fn chunk_bounds(coord: IVec3) -> (Vec3, Vec3) {
+    let min = (coord * CHUNK_SIZE_16).as_vec3();
+    (min, min + Vec3::splat(CHUNK_SIZE_16 as f32))
+}
+
+fn index(x: i32, y: i32, z: i32) -> usize {
+    debug_assert!((0..CHUNK_SIZE_16).contains(&x));
+    debug_assert!((0..CHUNK_SIZE_16).contains(&y));
+    debug_assert!((0..CHUNK_SIZE_16).contains(&z));
+    ((y * CHUNK_SIZE_16 + z) * CHUNK_SIZE_16 + x) as usize
+}
 
+impl Chunk {
+    /// `position` is the chunk coordinate, i.e. the world block position divided by
+    /// `CHUNK_SIZE_16`.
+    pub fn new(position: IVec3) -> Self {
         Self {
-            blocks: Vec::new(),
+            blocks: PalettedStorage::new(CHUNK_VOLUME, BlockType::AIR),
             position,
+            meshes: Vec::new(),
+            connections: FaceConnections::ALL,
+            lod: 0,
+            dirty: true,
         }
     }
 
-    pub fn connected_blocks(&mut self) {
-        for y in 0..CHUNK_SIZE_16 {
-            for x in 0..CHUNK_SIZE_16 {
-                for z in 0..CHUNK_SIZE_16 {
-                    let mut sides = RenderSides::default();
-
-                    if self.blocks[y as usize][x as usize][z as usize].is_some() {
-                        let top = y + 1 >= CHUNK_SIZE_16
-                            || self.blocks[y as usize + 1][x as usize][z as usize].is_none();
-                        if top {
-                            sides.top = Some(());
-                        }
-                        let bottom =
-                            y <= 0 || self.blocks[y as usize - 1][x as usize][z as usize].is_none();
-                        if bottom {
-                            sides.bottom = Some(());
-                        }
-                        let back = z + 1 >= CHUNK_SIZE_16
-                            || self.blocks[y as usize][x as usize][z as usize + 1].is_none();
-                        if back {
-                            sides.back = Some(());
-                        }
-                        let front =
-                            z <= 0 || self.blocks[y as usize][x as usize][z as usize - 1].is_none();
-                        if front {
-                            sides.front = Some(());
-                        }
-                        let left = x + 1 >= CHUNK_SIZE_16
-                            || self.blocks[y as usize][x as usize + 1][z as usize].is_none();
-                        if left {
-                            sides.left = Some(());
-                        }
-                        let right =
-                            x <= 0 || self.blocks[y as usize][x as usize - 1][z as usize].is_none();
-                        if right {
-                            sides.right = Some(());
-                        }
-                    }
-
-                    if let Some(block) = &mut self.blocks[y as usize][x as usize][z as usize] {
-                        block.make_mesh(&sides);
-                    }
-                }
-            }
+    pub fn position(&self) -> IVec3 {
+        self.position
+    }
+
+    /// World position of the block at local (0, 0, 0).
+    pub fn origin(&self) -> IVec3 {
+        self.position * CHUNK_SIZE_16
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockType {
+        self.blocks.get(index(x, y, z))
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block_type: BlockType) {
+        if self.blocks.set(index(x, y, z), block_type) != block_type {
+            self.dirty = true;
         }
     }
 
-    pub fn populate(&mut self, texture: &Texture2D) {
-        for y in 0..CHUNK_SIZE_16 {
-            let mut layer = Vec::new();
-            for x in 0..CHUNK_SIZE_16 {
-                let mut row = Vec::new();
-                for z in 0..CHUNK_SIZE_16 {
-                    let block_pos = vec3(x as f32, y as f32, z as f32);
-                    let block = if 1 == 1 {
-                        Some(Block::new(BlockType::Stone, block_pos, &texture))
-                    } else {
-                        None
-                    };
-                    row.push(block);
-                }
-                layer.push(row);
-            }
-            self.blocks.push(layer);
+    /// Whether the block at local `pos` is air or otherwise transparent. Positions
+    /// that fall outside this chunk are looked up through `is_air_outside`, which
+    /// gets their world position.
+    pub fn is_air_at(
+        &self,
+        pos: IVec3,
+        registry: &BlockRegistry,
+        is_air_outside: &impl Fn(IVec3) -> bool,
+    ) -> bool {
+        let inside = (0..CHUNK_SIZE_16).contains(&pos.x)
+            && (0..CHUNK_SIZE_16).contains(&pos.y)
+            && (0..CHUNK_SIZE_16).contains(&pos.z);
+        if inside {
+            registry.is_transparent(self.get(pos.x, pos.y, pos.z))
+        } else {
+            is_air_outside(self.origin() + pos)
+        }
+    }
+
+    /// Sides of the block at local (x, y, z) that face air or another
+    /// transparent block, see [`Chunk::is_air_at`].
+    pub fn visible_sides(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        registry: &BlockRegistry,
+        is_air_outside: &impl Fn(IVec3) -> bool,
+    ) -> RenderSides {
+        let is_air =
+            |x: i32, y: i32, z: i32| self.is_air_at(ivec3(x, y, z), registry, is_air_outside);
+        let mut sides = RenderSides::default();
+
+        if is_air(x, y + 1, z) {
+            sides.top = Some(());
+        }
+        if is_air(x, y - 1, z) {
+            sides.bottom = Some(());
+        }
+        if is_air(x, y, z + 1) {
+            sides.back = Some(());
+        }
+        if is_air(x, y, z - 1) {
+            sides.front = Some(());
+        }
+        if is_air(x + 1, y, z) {
+            sides.left = Some(());
+        }
+        if is_air(x - 1, y, z) {
+            sides.right = Some(());
+        }
+        sides
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Marks the chunk clean once its blocks have gone off to be meshed. Edits
+    /// have settled by then, so block types they stopped using are dropped from
+    /// the palette here, and the index width shrinks back if it can.
+    pub fn clear_dirty(&mut self) {
+        self.blocks.compact();
+        self.dirty = false;
+    }
+
+    /// Replaces the drawn meshes with freshly built ones.
+    pub fn set_meshes(&mut self, meshes: Vec<BlockMesh>, texture: &Texture2D) {
+        self.meshes = meshes
+            .into_iter()
+            .map(|mesh| mesh.into_mesh(texture))
+            .collect();
+    }
+
+    /// Which faces see each other through the chunk, as of its last meshing.
+    pub fn connections(&self) -> FaceConnections {
+        self.connections
+    }
+
+    pub fn set_connections(&mut self, connections: FaceConnections) {
+        self.connections = connections;
+    }
+
+    /// Level of detail the chunk is meshed at, see `lod::build_lod_mesh`.
+    pub fn lod(&self) -> u8 {
+        self.lod
+    }
+
+    /// Changes the level of detail, marking the chunk for remeshing if it differs.
+    pub fn set_lod(&mut self, lod: u8) {
+        if self.lod != lod {
+            self.lod = lod;
+            self.dirty = true;
+        }
+    }
+
+    /// A copy of the blocks, without meshes, that can be sent to another thread.
+    pub fn snapshot(&self) -> Chunk {
+        Self {
+            blocks: self.blocks.clone(),
+            position: self.position,
+            meshes: Vec::new(),
+            connections: self.connections,
+            lod: self.lod,
+            dirty: self.dirty,
         }
     }
-    pub fn from_fn(&mut self, texture: &Texture2D, f: impl Fn(i32, i32, i32) -> bool) {
+
+    /// Sets every block to `block_type`.
+    #[cfg(test)]
+    pub fn populate(&mut self, block_type: BlockType) {
+        self.blocks.fill(block_type);
+        self.dirty = true;
+    }
+    /// Sets every block where `f` holds to `block_type` and the rest to air.
+    #[cfg(test)]
+    pub fn fill_where(&mut self, block_type: BlockType, f: impl Fn(i32, i32, i32) -> bool) {
         for y in 0..CHUNK_SIZE_16 {
-            let mut layer = Vec::new();
             for x in 0..CHUNK_SIZE_16 {
-                let mut row = Vec::new();
                 for z in 0..CHUNK_SIZE_16 {
-                    let block_pos = vec3(x as f32, y as f32, z as f32);
-                    let block = if f(x, y, z) {
-                        Some(Block::new(BlockType::Stone, block_pos, &texture))
+                    let block_type = if f(x, y, z) {
+                        block_type
                     } else {
-                        None
+                        BlockType::AIR
                     };
-                    row.push(block);
+                    self.set(x, y, z, block_type);
                 }
-                layer.push(row);
             }
-            self.blocks.push(layer);
         }
     }
-    pub fn render(&mut self) {
+    pub fn render(&self) {
+        for mesh in &self.meshes {
+            draw_mesh(mesh);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::registry::test_registry;
+
+    const STONE: BlockType = BlockType(1);
+    const GRASS: BlockType = BlockType(3);
+
+    #[test]
+    fn get_and_set_round_trip() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        assert_eq!(chunk.get(3, 4, 5), BlockType::AIR);
+
+        chunk.set(3, 4, 5, GRASS);
+        chunk.set(15, 15, 15, STONE);
+        assert_eq!(chunk.get(3, 4, 5), GRASS);
+        assert_eq!(chunk.get(15, 15, 15), STONE);
+        assert_eq!(chunk.get(5, 4, 3), BlockType::AIR);
+        assert_eq!(chunk.blocks.bits(), 4);
+    }
+
+    #[test]
+    fn palette_shrinks_once_cleared_blocks_are_meshed() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
         for x in 0..CHUNK_SIZE_16 {
-            for y in 0..CHUNK_SIZE_16 {
-                for z in 0..CHUNK_SIZE_16 {
-                    if let Some(block) = &mut self.blocks[x as usize][y as usize][z as usize] {
-                        block.render_mesh();
-                    }
-                }
-            }
+            chunk.set(x, 0, 0, BlockType(x as u16 + 1));
+        }
+        chunk.set(0, 1, 0, STONE);
+        assert_eq!(chunk.blocks.bits(), 8);
+        chunk.clear_dirty();
+        assert_eq!(chunk.blocks.bits(), 8);
+
+        for x in 0..CHUNK_SIZE_16 {
+            chunk.set(x, 0, 0, BlockType::AIR);
         }
+        assert_eq!(chunk.blocks.bits(), 8);
+        chunk.clear_dirty();
+        assert_eq!(chunk.blocks.bits(), 1);
+        assert_eq!(chunk.blocks.palette_len(), 2);
+        assert_eq!(chunk.get(0, 1, 0), STONE);
+        assert_eq!(chunk.get(3, 0, 0), BlockType::AIR);
+    }
+
+    #[test]
+    fn fill_where_fills_solid_cells() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |_, y, _| y < 8);
+        assert_eq!(chunk.get(0, 7, 0), STONE);
+        assert_eq!(chunk.get(0, 8, 0), BlockType::AIR);
+        assert_eq!(chunk.blocks.bits(), 1);
+    }
+
+    #[test]
+    fn visible_sides_consult_outside_lookup_on_borders() {
+        let mut chunk = Chunk::new(ivec3(1, 0, 0));
+        chunk.set(0, 0, 0, STONE);
+        chunk.set(1, 0, 0, STONE);
+
+        let registry = test_registry();
+        let solid_outside = |_: IVec3| false;
+        let sides = chunk.visible_sides(0, 0, 0, &registry, &solid_outside);
+        assert!(sides.right.is_none());
+        assert!(sides.bottom.is_none());
+        assert!(sides.front.is_none());
+        assert!(sides.left.is_none());
+        assert!(sides.top.is_some());
+        assert!(sides.back.is_some());
+
+        let air_west = |pos: IVec3| pos.x < 16;
+        let sides = chunk.visible_sides(0, 0, 0, &registry, &air_west);
+        assert!(sides.right.is_some());
+        assert!(sides.bottom.is_none());
+    }
+
+    #[test]
+    fn outside_lookup_receives_world_positions() {
+        let mut chunk = Chunk::new(ivec3(-1, 2, 0));
+        chunk.set(15, 0, 0, STONE);
+        let asked = std::cell::RefCell::new(Vec::new());
+        chunk.visible_sides(15, 0, 0, &test_registry(), &|pos: IVec3| {
+            asked.borrow_mut().push(pos);
+            true
+        });
+        let mut asked = asked.into_inner();
+        asked.sort_by_key(|pos| (pos.x, pos.y, pos.z));
+        assert_eq!(
+            asked,
+            vec![ivec3(-1, 31, 0), ivec3(-1, 32, -1), ivec3(0, 32, 0)]
+        );
     }
 }
@@ -1,26 +1,70 @@
 use macroquad::{
     camera::{set_camera, Camera3D, Projection},
     input::{
-        is_key_down, is_key_pressed, is_key_released, mouse_position, set_cursor_grab, show_mouse,
-        KeyCode,
+        is_key_down, is_key_pressed, is_key_released, is_mouse_button_pressed, mouse_position,
+        set_cursor_grab, show_mouse, KeyCode, MouseButton,
     },
-    math::{vec3, Vec2, Vec3},
+    math::{vec3, IVec3, Vec2, Vec3},
     time::get_frame_time,
     window::{screen_height, screen_width},
 };
 
-const MOVE_SPEED: f32 = 0.1;
-const LOOK_SPEED: f32 = 0.09;
+use super::{aabb::Aabb, physics::Body, timestep::FixedTimestep};
+
+/// Flying speed, in blocks per second.
+const MOVE_SPEED: f32 = 10.0;
+/// Radians turned per pixel the mouse moves.
+const LOOK_SPEED: f32 = 0.0015;
 const FLY_SPEED: f32 = 0.06;
+/// Half the width of the player's bounding box.
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+/// Height of the camera above the player's feet.
+const EYE_HEIGHT: f32 = 1.62;
+
+/// How the player gets around. F switches between the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Movement {
+    /// On foot, under gravity, and stopped by solid blocks.
+    Walking,
+    /// Free flight straight through everything.
+    Flying,
+}
+
+/// Movement keys held down in a frame, applied to every tick the frame runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MoveInput {
+    /// Horizontal direction to move in, at most one long.
+    pub direction: Vec3,
+    /// Jump when walking, rise when flying.
+    pub up: bool,
+    /// Sink when flying.
+    pub down: bool,
+}
+
+/// What a mouse click asks to do to the targeted block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockAction {
+    Break,
+    Place,
+}
 
 pub struct Player {
+    /// Camera position as of the last simulation tick.
     pub position: Vec3,
+    /// Camera position as of the tick before, drawn from while the next tick
+    /// is still in progress.
+    previous_position: Vec3,
+    /// How far between `previous_position` and `position` the frame is drawn.
+    alpha: f32,
     pub yaw: f32,
     pub pitch: f32,
     pub grabbed: bool,
     last_mouse_position: Vec2,
     pub fovy: f32,
     pub projection: Projection,
+    pub movement: Movement,
+    body: Body,
 }
 
 impl Player {
@@ -29,45 +73,84 @@ impl Player {
         show_mouse(true);
         Self {
             position: Vec3::new(0.0, 0.0, 0.0),
+            previous_position: Vec3::new(0.0, 0.0, 0.0),
+            alpha: 1.0,
             yaw: 0.0,
             pitch: 0.0,
             grabbed: false,
             last_mouse_position: mouse_position().into(),
             fovy: 45.0,
             projection: Projection::Perspective,
+            movement: Movement::Walking,
+            body: Body::default(),
         }
     }
 
-    fn update_pos(&mut self) {
-        let world_up = vec3(0.0, 1.0, 0.0);
+    /// Switches to `movement`, starting it at rest.
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+        self.body = Body::default();
+    }
+
+    /// Moves the player to `position` without drawing the way there.
+    pub fn teleport(&mut self, position: Vec3) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    /// Where the camera is drawn: between the last two ticks, as far along as
+    /// the frame is into the next one.
+    pub fn eye(&self) -> Vec3 {
+        self.previous_position.lerp(self.position, self.alpha)
+    }
+
+    fn read_input(&self) -> MoveInput {
         let front_velocity = vec3(self.yaw.cos(), 0.0, self.yaw.sin());
         let left_velocity = vec3(self.yaw.sin(), 0.0, -self.yaw.cos());
-        let mut velocity = vec3(0.0, 0.0, 0.0);
+        let mut direction = vec3(0.0, 0.0, 0.0);
 
         if is_key_down(KeyCode::W) {
-            velocity += front_velocity;
+            direction += front_velocity;
         }
         if is_key_down(KeyCode::S) {
-            velocity -= front_velocity;
+            direction -= front_velocity;
         }
         if is_key_down(KeyCode::A) {
-            velocity += left_velocity;
+            direction += left_velocity;
         }
         if is_key_down(KeyCode::D) {
-            velocity -= left_velocity;
+            direction -= left_velocity;
         }
-        if is_key_down(KeyCode::Space) {
-            velocity += world_up;
+        MoveInput {
+            direction: direction.normalize_or_zero(),
+            up: is_key_down(KeyCode::Space),
+            down: is_key_down(KeyCode::LeftShift),
         }
-        if is_key_down(KeyCode::LeftShift) {
-            velocity -= world_up;
-        }
-        if velocity.x + velocity.y + velocity.z == 0.0 {
-            velocity = vec3(0.0, 0.0, 0.0);
-        } else {
-            velocity = velocity.normalize();
+    }
+
+    /// Advances the player by one simulation tick of `dt` seconds. Depends on
+    /// nothing but its arguments and the player's state, so the same inputs
+    /// give the same result however the ticks fall across frames.
+    pub fn tick(&mut self, input: MoveInput, dt: f32, is_solid: impl Fn(IVec3) -> bool) {
+        self.previous_position = self.position;
+        match self.movement {
+            Movement::Walking => {
+                let bounds = self.bounds();
+                let jump = input.up;
+                self.position += self.body.step(bounds, input.direction, jump, dt, is_solid);
+            }
+            Movement::Flying => {
+                let world_up = vec3(0.0, 1.0, 0.0);
+                let mut velocity = input.direction;
+                if input.up {
+                    velocity += world_up;
+                }
+                if input.down {
+                    velocity -= world_up;
+                }
+                self.position += velocity.normalize_or_zero() * MOVE_SPEED * dt;
+            }
         }
-        self.position += velocity * MOVE_SPEED * get_frame_time() * 100.0;
     }
     fn update_look(&mut self) {
         if is_key_down(KeyCode::LeftAlt) {
@@ -81,7 +164,6 @@ impl Player {
             set_cursor_grab(self.grabbed);
             show_mouse(!self.grabbed);
         }
-        let delta = get_frame_time();
         let mouse_position: Vec2 = mouse_position().into();
         let mouse_delta = mouse_position - self.last_mouse_position;
         self.last_mouse_position = mouse_position;
@@ -89,39 +171,191 @@ impl Player {
             return;
         }
 
-        self.yaw += mouse_delta.x * delta * LOOK_SPEED;
-        self.pitch += mouse_delta.y * delta * -LOOK_SPEED;
+        // Pixel deltas already add up to the same turn at any frame rate.
+        self.yaw += mouse_delta.x * LOOK_SPEED;
+        self.pitch += mouse_delta.y * -LOOK_SPEED;
         self.pitch = if self.pitch > 1.5 { 1.5 } else { self.pitch };
         self.pitch = if self.pitch < -1.5 { -1.5 } else { self.pitch };
     }
-    fn update_mouse_grab(&mut self) {
-    }
-    fn get_camera_orientation(&self) -> (Vec3, Vec3) {
-        let world_up = vec3(0.0, 1.0, 0.0);
-        let front = vec3(
+    fn update_mouse_grab(&mut self) {}
+    /// Unit vector the camera looks along.
+    pub fn front(&self) -> Vec3 {
+        vec3(
             self.yaw.cos() * self.pitch.cos(),
             self.pitch.sin(),
             self.yaw.sin() * self.pitch.cos(),
         )
-        .normalize();
+        .normalize()
+    }
+    fn get_camera_orientation(&self) -> (Vec3, Vec3) {
+        let world_up = vec3(0.0, 1.0, 0.0);
+        let front = self.front();
         let right = front.cross(world_up).normalize();
         let up = right.cross(front).normalize();
-        let target = self.position + front;
+        let target = self.eye() + front;
         (up, target)
     }
-    pub fn update(&mut self) {
-        self.update_mouse_grab();
-        self.update_pos();
-        self.update_look();
+    /// Box the player's body fills, below and around the camera at `position`.
+    pub fn bounds(&self) -> Aabb {
+        let feet = self.position - vec3(0.0, EYE_HEIGHT, 0.0);
+        Aabb::new(
+            feet - vec3(HALF_WIDTH, 0.0, HALF_WIDTH),
+            feet + vec3(HALF_WIDTH, HEIGHT, HALF_WIDTH),
+        )
+    }
+    /// Left click breaks, right click places. Clicks only count while the
+    /// cursor is grabbed, so using the debug UI leaves the world alone.
+    pub fn block_action(&self) -> Option<BlockAction> {
+        if !self.grabbed {
+            None
+        } else if is_mouse_button_pressed(MouseButton::Left) {
+            Some(BlockAction::Break)
+        } else if is_mouse_button_pressed(MouseButton::Right) {
+            Some(BlockAction::Place)
+        } else {
+            None
+        }
+    }
+    pub fn camera(&self) -> Camera3D {
         let (up, target) = self.get_camera_orientation();
-        set_camera(&Camera3D {
-            position: self.position,
+        Camera3D {
+            position: self.eye(),
             up,
             target,
             fovy: self.fovy, // min: 38.0 max: 47.0
             projection: self.projection,
             aspect: Some(screen_width() / screen_height()),
             ..Default::default()
-        });
+        }
+    }
+    /// Turns the player from this frame's mouse movement, then runs the
+    /// simulation ticks `timestep` has due with this frame's keys. `is_solid`
+    /// tells which blocks stop a walking player.
+    pub fn update(&mut self, timestep: &mut FixedTimestep, is_solid: impl Fn(IVec3) -> bool) {
+        self.update_mouse_grab();
+        self.update_look();
+        if is_key_pressed(KeyCode::F) {
+            self.set_movement(match self.movement {
+                Movement::Walking => Movement::Flying,
+                Movement::Flying => Movement::Walking,
+            });
+        }
+        let input = self.read_input();
+        for _ in 0..timestep.advance(get_frame_time()) {
+            self.tick(input, timestep.step(), &is_solid);
+        }
+        self.alpha = timestep.alpha();
+        set_camera(&self.camera());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::IVec3;
+
+    /// Ground everywhere, a one block step from x 4 and a wall from x 8.
+    fn world(pos: IVec3) -> bool {
+        pos.y < 1 || (pos.x >= 4 && pos.y == 1) || pos.x >= 8
+    }
+
+    /// A player standing on the ground, built without a window.
+    fn player() -> Player {
+        let position = vec3(0.5, 1.0 + EYE_HEIGHT, 0.5);
+        Player {
+            position,
+            previous_position: position,
+            alpha: 1.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            grabbed: false,
+            last_mouse_position: Vec2::ZERO,
+            fovy: 45.0,
+            projection: Projection::Perspective,
+            movement: Movement::Walking,
+            body: Body::default(),
+        }
+    }
+
+    /// Keys held on each tick: walking into the step and hopping up it, then
+    /// diagonally into the wall, then flying up and away.
+    fn script(tick: u32) -> MoveInput {
+        let direction = match tick {
+            0..90 => Vec3::X,
+            90..200 => vec3(1.0, 0.0, 1.0).normalize(),
+            _ => Vec3::NEG_Z,
+        };
+        MoveInput {
+            direction,
+            up: (tick < 160 && tick % 40 == 20) || tick >= 240,
+            down: false,
+        }
+    }
+
+    /// Runs `ticks` ticks of the script, with frames taking `frame_times` in
+    /// turn, and returns the player.
+    fn simulate(frame_times: &[f32], ticks: u32) -> Player {
+        let mut player = player();
+        let mut timestep = FixedTimestep::default();
+        let mut done = 0;
+        for &frame_time in frame_times.iter().cycle() {
+            for _ in 0..timestep.advance(frame_time) {
+                if done == ticks {
+                    return player;
+                }
+                if done == 200 {
+                    player.set_movement(Movement::Flying);
+                }
+                player.tick(script(done), timestep.step(), world);
+                done += 1;
+            }
+            if done == ticks {
+                return player;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn the_simulation_is_the_same_at_any_frame_rate() {
+        let reference = simulate(&[1.0 / 60.0], 300);
+        // Up the step and against the wall before taking off.
+        let walked = simulate(&[1.0 / 60.0], 200);
+        assert!((walked.position.y - 2.0 - EYE_HEIGHT).abs() < 1e-4);
+        assert!((walked.bounds().max.x - 8.0).abs() < 1e-4);
+        assert!(reference.position.y > walked.position.y + 1.0);
+
+        for frame_times in [
+            &[1.0 / 30.0][..],
+            &[1.0 / 144.0],
+            &[1.0 / 240.0],
+            &[0.004, 0.03, 0.011, 0.05, 1.0 / 60.0, 0.0],
+        ] {
+            let player = simulate(frame_times, 300);
+            assert_eq!(player.position, reference.position, "{frame_times:?}");
+            assert_eq!(player.previous_position, reference.previous_position);
+            assert_eq!(player.body, reference.body);
+        }
+    }
+
+    #[test]
+    fn frames_are_drawn_between_the_last_two_ticks() {
+        let mut player = player();
+        player.set_movement(Movement::Flying);
+        let start = player.position;
+        let input = MoveInput {
+            direction: Vec3::X,
+            ..MoveInput::default()
+        };
+        player.tick(input, 0.1, world);
+        assert_eq!(player.position, start + vec3(1.0, 0.0, 0.0));
+        player.alpha = 0.25;
+        assert_eq!(player.eye(), start + vec3(0.25, 0.0, 0.0));
+        player.alpha = 1.0;
+        assert_eq!(player.eye(), player.position);
+
+        player.alpha = 0.5;
+        player.teleport(vec3(3.0, 4.0, 5.0));
+        assert_eq!(player.eye(), vec3(3.0, 4.0, 5.0));
     }
 }
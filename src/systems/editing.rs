@@ -0,0 +1,237 @@
+use crate::systems::{
+    aabb::Aabb,
+    blocks::BlockType,
+    raycast::RayHit,
+    registry::BlockRegistry,
+    world::{world_to_chunk, World},
+};
+use macroquad::prelude::*;
+
+/// Why a block could not be placed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaceError {
+    /// The ray started inside the block, so there is no face to place against.
+    NoFace,
+    /// Something is already drawn where the block would go.
+    Occupied,
+    /// The block would go into a chunk that is not loaded yet.
+    NotLoaded,
+    /// The block would overlap the player.
+    Blocked,
+}
+
+/// Removes the block `hit` points at and returns what it was, or `None` if the
+/// registry says it cannot be broken. Only its chunk, and the chunks across a
+/// border it lies on, are remeshed.
+pub fn break_block(world: &mut World, registry: &BlockRegistry, hit: &RayHit) -> Option<BlockType> {
+    let broken = world.get_block(hit.block);
+    if !registry.is_breakable(broken) {
+        return None;
+    }
+    world.set_block(hit.block, BlockType::AIR);
+    Some(broken)
+}
+
+/// Places `block_type` against the face `hit` points at, unless it would
+/// overlap `player` or replace a drawn block. Returns where it went.
+pub fn place_block(
+    world: &mut World,
+    registry: &BlockRegistry,
+    hit: &RayHit,
+    block_type: BlockType,
+    player: &Aabb,
+) -> Result<IVec3, PlaceError> {
+    if hit.normal == IVec3::ZERO {
+        return Err(PlaceError::NoFace);
+    }
+    let pos = hit.block + hit.normal;
+    if world.chunk(world_to_chunk(pos).0).is_none() {
+        return Err(PlaceError::NotLoaded);
+    }
+    if registry.is_drawn(world.get_block(pos)) {
+        return Err(PlaceError::Occupied);
+    }
+    if Aabb::block(pos).intersects(player) {
+        return Err(PlaceError::Blocked);
+    }
+    world.set_block(pos, block_type);
+    Ok(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{
+        atlas::{AtlasLayout, DEFAULT_ATLAS_TILES},
+        chunks::Chunk,
+        raycast::raycast,
+        registry::{test_registry, DEFAULT_BLOCKS},
+    };
+
+    const STONE: BlockType = BlockType(1);
+    const DIRT: BlockType = BlockType(2);
+
+    /// Two loaded chunks side by side along x, with a stone floor at y 0, and
+    /// all dirty flags cleared.
+    fn world() -> World {
+        let mut world = World::new();
+        for coord in [IVec3::ZERO, IVec3::X, IVec3::NEG_X] {
+            world.insert(Chunk::new(coord));
+        }
+        for x in -16..32 {
+            for z in 0..16 {
+                world.set_block(ivec3(x, 0, z), STONE);
+            }
+        }
+        clear_dirty(&mut world);
+        world
+    }
+
+    fn clear_dirty(world: &mut World) {
+        world.take_dirty(usize::MAX, IVec3::ZERO, &test_registry());
+    }
+
+    fn dirty(world: &World) -> Vec<IVec3> {
+        let mut dirty: Vec<IVec3> = world
+            .coords()
+            .filter(|&coord| world.chunk(coord).unwrap().is_dirty())
+            .collect();
+        dirty.sort_by_key(|coord| coord.to_array());
+        dirty
+    }
+
+    fn hit(block: IVec3, normal: IVec3) -> RayHit {
+        RayHit {
+            block,
+            normal,
+            distance: 1.0,
+        }
+    }
+
+    /// A player standing with their feet at `feet`.
+    fn player(feet: Vec3) -> Aabb {
+        Aabb::new(feet - vec3(0.25, 0.0, 0.25), feet + vec3(0.25, 1.75, 0.25))
+    }
+
+    #[test]
+    fn breaking_remeshes_only_the_chunks_it_touches() {
+        let mut world = world();
+        let looking_down = raycast(vec3(5.5, 3.0, 5.5), Vec3::NEG_Y, 6.0, |pos| {
+            world.get_block(pos) != BlockType::AIR
+        })
+        .unwrap();
+        let registry = test_registry();
+        assert_eq!(
+            break_block(&mut world, &registry, &looking_down),
+            Some(STONE)
+        );
+        assert_eq!(world.get_block(ivec3(5, 0, 5)), BlockType::AIR);
+        assert_eq!(dirty(&world), [IVec3::ZERO]);
+
+        clear_dirty(&mut world);
+        break_block(&mut world, &registry, &hit(ivec3(15, 0, 3), IVec3::Y));
+        assert_eq!(dirty(&world), [IVec3::ZERO, IVec3::X]);
+    }
+
+    #[test]
+    fn unbreakable_blocks_stay() {
+        let atlas = AtlasLayout::new(1, DEFAULT_ATLAS_TILES.len() as u32, &DEFAULT_ATLAS_TILES);
+        let source = format!(
+            "{DEFAULT_BLOCKS}\n[[block]]\nid = 12\nname = \"bedrock\"\nhardness = -1.0\n\
+             textures = {{ all = \"stone\" }}\n"
+        );
+        let registry = BlockRegistry::from_toml(&source, &atlas).unwrap();
+        let bedrock = registry.require("bedrock").unwrap();
+        let mut world = world();
+        world.set_block(ivec3(3, 0, 3), bedrock);
+        clear_dirty(&mut world);
+
+        assert_eq!(
+            break_block(&mut world, &registry, &hit(ivec3(3, 0, 3), IVec3::Y)),
+            None
+        );
+        assert_eq!(world.get_block(ivec3(3, 0, 3)), bedrock);
+        assert!(dirty(&world).is_empty());
+        assert_eq!(
+            break_block(&mut world, &registry, &hit(ivec3(4, 0, 3), IVec3::Y)),
+            Some(STONE)
+        );
+    }
+
+    #[test]
+    fn placing_goes_against_the_hit_face() {
+        let registry = test_registry();
+        let mut world = world();
+        let far_away = player(vec3(100.0, 1.0, 100.0));
+        let placed = place_block(
+            &mut world,
+            &registry,
+            &hit(ivec3(4, 0, 4), IVec3::Y),
+            DIRT,
+            &far_away,
+        );
+        assert_eq!(placed, Ok(ivec3(4, 1, 4)));
+        assert_eq!(world.get_block(ivec3(4, 1, 4)), DIRT);
+        assert_eq!(dirty(&world), [IVec3::ZERO]);
+
+        // On the chunk border.
+        clear_dirty(&mut world);
+        let placed = place_block(
+            &mut world,
+            &registry,
+            &hit(ivec3(0, 0, 4), IVec3::Y),
+            DIRT,
+            &far_away,
+        );
+        assert_eq!(placed, Ok(ivec3(0, 1, 4)));
+        assert_eq!(dirty(&world), [IVec3::NEG_X, IVec3::ZERO]);
+    }
+
+    #[test]
+    fn placing_is_refused_where_it_cannot_go() {
+        let registry = test_registry();
+        let mut world = world();
+        let far_away = player(vec3(100.0, 1.0, 100.0));
+        let mut place = |hit: RayHit| place_block(&mut world, &registry, &hit, DIRT, &far_away);
+        assert_eq!(
+            place(hit(ivec3(4, 0, 4), IVec3::ZERO)),
+            Err(PlaceError::NoFace)
+        );
+        assert_eq!(
+            place(hit(ivec3(4, 1, 4), IVec3::NEG_Y)),
+            Err(PlaceError::Occupied)
+        );
+        assert_eq!(
+            place(hit(ivec3(4, 0, 15), IVec3::Z)),
+            Err(PlaceError::NotLoaded)
+        );
+        assert_eq!(world.chunk_count(), 3);
+    }
+
+    #[test]
+    fn placing_never_overlaps_the_player() {
+        let registry = test_registry();
+        let mut world = world();
+        let standing = player(vec3(4.5, 1.0, 4.5));
+        let below_feet = hit(ivec3(4, 0, 4), IVec3::Y);
+        assert_eq!(
+            place_block(&mut world, &registry, &below_feet, DIRT, &standing),
+            Err(PlaceError::Blocked)
+        );
+        // At head height, overlapping by a sliver of the width.
+        let beside = hit(ivec3(5, 2, 4), IVec3::NEG_X);
+        let edge = player(vec3(4.0, 1.0, 4.5));
+        assert_eq!(
+            place_block(&mut world, &registry, &beside, DIRT, &edge),
+            Err(PlaceError::Blocked)
+        );
+        assert_eq!(world.get_block(ivec3(4, 1, 4)), BlockType::AIR);
+
+        // Touching the block is fine.
+        let touching = player(vec3(5.25, 1.0, 4.5));
+        assert_eq!(
+            place_block(&mut world, &registry, &below_feet, DIRT, &touching),
+            Ok(ivec3(4, 1, 4))
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use macroquad::prelude::*;
+
+/// Clip distances `Camera3D` uses.
+const Z_NEAR: f32 = 0.01;
+const Z_FAR: f32 = 10000.0;
+
+/// The six planes bounding what a camera sees. Each plane is stored as
+/// `(normal, distance)` in a `Vec4`, with the normal pointing into the frustum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a combined projection and view matrix. Works the
+    /// same for perspective and orthographic projections, since both map the
+    /// frustum onto the -1..1 clip cube.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (x, y, z, w) = (matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            // Normalised, so plane distances are in world units.
+            plane / plane.truncate().length()
+        });
+        Self { planes }
+    }
+
+    /// The frustum of `camera`, built from the same matrix `Camera3D` hands to
+    /// the GPU. Unlike `Camera::matrix` this only reads the screen size when the
+    /// camera has no aspect, so it also works without a window.
+    pub fn from_camera(camera: &Camera3D) -> Self {
+        let aspect = camera
+            .aspect
+            .unwrap_or_else(|| screen_width() / screen_height());
+        let projection = match camera.projection {
+            Projection::Perspective => Mat4::perspective_rh_gl(camera.fovy, aspect, Z_NEAR, Z_FAR),
+            Projection::Orthographics => {
+                let top = camera.fovy / 2.0;
+                let right = top * aspect;
+                Mat4::orthographic_rh_gl(-right, right, -top, top, Z_NEAR, Z_FAR)
+            }
+        };
+        let view = Mat4::look_at_rh(camera.position, camera.target, camera.up);
+        Self::from_matrix(projection * view)
+    }
+
+    /// Whether any of the axis aligned box from `min` to `max` may be visible.
+    /// Boxes near a frustum corner can pass without being visible; boxes that are
+    /// visible never fail.
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            // The box corner farthest along the plane normal.
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(projection: Projection) -> Camera3D {
+        // Looking down +x from the origin.
+        Camera3D {
+            position: Vec3::ZERO,
+            target: Vec3::X,
+            up: Vec3::Y,
+            fovy: 1.0,
+            aspect: Some(2.0),
+            projection,
+            ..Default::default()
+        }
+    }
+
+    /// A cube of side `size` centred on `center`.
+    fn visible(frustum: &Frustum, center: Vec3, size: f32) -> bool {
+        let half = Vec3::splat(size / 2.0);
+        frustum.intersects_aabb(center - half, center + half)
+    }
+
+    #[test]
+    fn perspective_keeps_boxes_in_view() {
+        let frustum = Frustum::from_camera(&camera(Projection::Perspective));
+        assert!(visible(&frustum, vec3(20.0, 0.0, 0.0), 1.0));
+        // The camera sits inside this one.
+        assert!(visible(&frustum, Vec3::ZERO, 16.0));
+        // tan(0.5) * 100 = 54.6 up and down, twice that to the sides.
+        assert!(visible(&frustum, vec3(100.0, 50.0, 0.0), 1.0));
+        assert!(visible(&frustum, vec3(100.0, 0.0, 105.0), 1.0));
+        // Straddling the top plane.
+        assert!(visible(&frustum, vec3(100.0, 58.0, 0.0), 8.0));
+    }
+
+    #[test]
+    fn perspective_culls_boxes_out_of_view() {
+        let frustum = Frustum::from_camera(&camera(Projection::Perspective));
+        assert!(!visible(&frustum, vec3(-20.0, 0.0, 0.0), 16.0));
+        assert!(!visible(&frustum, vec3(100.0, 60.0, 0.0), 1.0));
+        assert!(!visible(&frustum, vec3(100.0, -60.0, 0.0), 1.0));
+        assert!(!visible(&frustum, vec3(100.0, 0.0, 115.0), 1.0));
+        assert!(!visible(&frustum, vec3(100.0, 0.0, -115.0), 1.0));
+        // Past the far plane.
+        assert!(!visible(&frustum, vec3(20_000.0, 0.0, 0.0), 16.0));
+        // Close in, the view is narrow.
+        assert!(!visible(&frustum, vec3(5.0, 8.0, 0.0), 2.0));
+    }
+
+    #[test]
+    fn orthographic_keeps_a_box_shaped_view() {
+        // Half a unit up and down, one to the sides, at any distance.
+        let frustum = Frustum::from_camera(&camera(Projection::Orthographics));
+        assert!(visible(&frustum, vec3(5.0, 0.0, 0.0), 0.5));
+        assert!(visible(&frustum, vec3(500.0, 0.4, 0.9), 0.1));
+        assert!(visible(&frustum, vec3(500.0, 0.7, 0.0), 0.5));
+        assert!(!visible(&frustum, vec3(500.0, 0.8, 0.0), 0.5));
+        assert!(!visible(&frustum, vec3(5.0, 0.0, 1.3), 0.5));
+        assert!(!visible(&frustum, vec3(5.0, 0.0, -1.3), 0.5));
+        assert!(!visible(&frustum, vec3(-5.0, 0.0, 0.0), 0.5));
+        // What a perspective camera would see off to the side at this distance.
+        assert!(!visible(&frustum, vec3(100.0, 0.0, 50.0), 1.0));
+    }
+
+    #[test]
+    fn follows_the_camera() {
+        let camera = Camera3D {
+            position: vec3(0.0, 40.0, 0.0),
+            target: vec3(0.0, 40.0, 1.0),
+            ..camera(Projection::Perspective)
+        };
+        let frustum = Frustum::from_camera(&camera);
+        assert!(visible(&frustum, vec3(0.0, 40.0, 30.0), 16.0));
+        assert!(!visible(&frustum, vec3(30.0, 40.0, 0.0), 16.0));
+        assert!(!visible(&frustum, vec3(0.0, 40.0, -30.0), 16.0));
+    }
+}
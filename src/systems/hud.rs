@@ -0,0 +1,91 @@
+use crate::systems::{blocks::BlockSides, raycast::RayHit, render_utils::face_corners};
+use macroquad::prelude::*;
+
+/// How far the outline and the face highlight sit off the block, so they do not
+/// fight with its faces for depth.
+const INSET: f32 = 0.002;
+const OUTLINE: Color = Color::new(0.0, 0.0, 0.0, 0.8);
+const HIGHLIGHT: Color = Color::new(1.0, 1.0, 1.0, 0.25);
+const CROSSHAIR_SIZE: f32 = 10.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+/// Side of a block whose normal is `normal`, if it is a unit axis vector.
+pub fn side_of(normal: IVec3) -> Option<BlockSides> {
+    BlockSides::ALL
+        .into_iter()
+        .find(|side| side.normal().as_ivec3() == normal)
+}
+
+/// Corners of the face of the targeted block the ray hit, lifted just off the
+/// block, in the order `face_corners` gives them. None if the ray started
+/// inside the block.
+pub fn target_face(hit: &RayHit) -> Option<[Vec3; 4]> {
+    let side = side_of(hit.normal)?;
+    let lift = hit.normal.as_vec3() * INSET;
+    Some(face_corners(side).map(|corner| hit.block.as_vec3() + corner + lift))
+}
+
+/// Outlines the targeted block and highlights the face the ray hit. Needs the
+/// 3D camera and the default material.
+pub fn draw_target(hit: &RayHit) {
+    let center = hit.block.as_vec3() + Vec3::splat(0.5);
+    draw_cube_wires(center, Vec3::splat(1.0 + 2.0 * INSET), OUTLINE);
+    if let Some([a, b, _, d]) = target_face(hit) {
+        draw_affine_parallelogram(a, b - a, d - a, None, HIGHLIGHT);
+    }
+}
+
+/// Draws a cross at the centre of the screen. Needs the default 2D camera.
+pub fn draw_crosshair() {
+    let center = vec2(screen_width(), screen_height()) / 2.0;
+    for axis in [Vec2::X, Vec2::Y] {
+        let start = center - axis * CROSSHAIR_SIZE;
+        let end = center + axis * CROSSHAIR_SIZE;
+        draw_line(start.x, start.y, end.x, end.y, CROSSHAIR_THICKNESS, WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(block: IVec3, normal: IVec3) -> RayHit {
+        RayHit {
+            block,
+            normal,
+            distance: 2.0,
+        }
+    }
+
+    #[test]
+    fn highlights_the_face_the_ray_entered() {
+        let face = target_face(&hit(ivec3(3, -2, 7), IVec3::Y)).unwrap();
+        let top = -2.0 + 1.0 + INSET;
+        assert_eq!(
+            face,
+            [
+                vec3(3.0, top, 7.0),
+                vec3(4.0, top, 7.0),
+                vec3(4.0, top, 8.0),
+                vec3(3.0, top, 8.0)
+            ]
+        );
+
+        for side in BlockSides::ALL {
+            let normal = side.normal().as_ivec3();
+            let face = target_face(&hit(IVec3::ZERO, normal)).unwrap();
+            for corner in face {
+                // On the outside of the face, and within the face's square.
+                let center = Vec3::splat(0.5);
+                let along = (corner - center).dot(normal.as_vec3());
+                assert!((along - 0.5 - INSET).abs() < 1e-6, "{side:?}");
+                assert!((corner - center).abs().max_element() <= 0.5 + INSET);
+            }
+        }
+    }
+
+    #[test]
+    fn no_face_from_inside_a_block() {
+        assert_eq!(target_face(&hit(IVec3::ONE, IVec3::ZERO)), None);
+    }
+}
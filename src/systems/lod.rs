@@ -0,0 +1,319 @@
+use crate::{
+    systems::{
+        blocks::{BlockMesh, BlockSides, BlockType},
+        chunks::Chunk,
+        meshing::MeshBuilder,
+        registry::BlockRegistry,
+        render_utils::{mesh_side, NO_AO},
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+/// The coarsest level of detail: cells of 8x8x8 blocks.
+pub const MAX_LOD: u8 = 3;
+
+/// Distances at which chunk meshes switch to coarser levels of detail.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodSettings {
+    /// Horizontal distances, in chunks from the player's chunk, past which
+    /// chunks are meshed from cells of 2, 4 and 8 blocks.
+    pub distances: [i32; MAX_LOD as usize],
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self::spaced(4)
+    }
+}
+
+impl LodSettings {
+    /// A level every `distance` chunks.
+    pub fn spaced(distance: i32) -> Self {
+        Self {
+            distances: [distance, distance * 2, distance * 3],
+        }
+    }
+
+    /// Level of detail of the chunk `offset` chunks away from the player's.
+    pub fn level(&self, offset: IVec3) -> u8 {
+        let distance_squared = offset.x * offset.x + offset.z * offset.z;
+        self.distances
+            .iter()
+            .filter(|&&distance| distance_squared > distance * distance)
+            .count() as u8
+    }
+}
+
+/// Side, in blocks, of the cells a chunk is meshed from at `level`.
+pub fn cell_size(level: u8) -> i32 {
+    1 << level
+}
+
+/// Cells of `chunk` at `level`, indexed like the blocks of a chunk `cells` wide.
+///
+/// A cell is filled if any block in it is drawn, so a coarse chunk covers at
+/// least everything the full resolution one does and never opens a hole next to
+/// a neighbour at another level. It shows the block most of its columns have on
+/// top, so grass stays grass from afar.
+pub fn downsample(chunk: &Chunk, level: u8, registry: &BlockRegistry) -> Vec<Option<BlockType>> {
+    let size = cell_size(level);
+    let cells = CHUNK_SIZE_16 / size;
+    let mut result = Vec::with_capacity((cells * cells * cells) as usize);
+    let mut tops: Vec<(BlockType, u32)> = Vec::new();
+    for cy in 0..cells {
+        for cz in 0..cells {
+            for cx in 0..cells {
+                tops.clear();
+                for z in cz * size..(cz + 1) * size {
+                    for x in cx * size..(cx + 1) * size {
+                        let top = (cy * size..(cy + 1) * size)
+                            .rev()
+                            .map(|y| chunk.get(x, y, z))
+                            .find(|&block| registry.is_drawn(block));
+                        let Some(top) = top else {
+                            continue;
+                        };
+                        match tops.iter_mut().find(|(block, _)| *block == top) {
+                            Some((_, count)) => *count += 1,
+                            None => tops.push((top, 1)),
+                        }
+                    }
+                }
+                // The first block seen wins ties, so the result is deterministic.
+                let best = tops
+                    .iter()
+                    .rev()
+                    .max_by_key(|(_, count)| *count)
+                    .map(|(block, _)| *block);
+                result.push(best);
+            }
+        }
+    }
+    result
+}
+
+/// Builds the meshes of `chunk` at `level` from the cells `downsample` makes,
+/// one quad per visible cell face, in world space.
+///
+/// Faces between cells are culled against the neighbouring cell. Faces on the
+/// chunk border are culled only if every block behind them is solid at full
+/// resolution, which is what the neighbour chunk covers whatever its level.
+pub fn build_lod_mesh(
+    chunk: &Chunk,
+    level: u8,
+    registry: &BlockRegistry,
+    is_air_outside: &impl Fn(IVec3) -> bool,
+) -> Vec<BlockMesh> {
+    let size = cell_size(level);
+    let cells = CHUNK_SIZE_16 / size;
+    let grid = downsample(chunk, level, registry);
+    let cell_at = |cell: IVec3| grid[((cell.y * cells + cell.z) * cells + cell.x) as usize];
+    let origin = chunk.origin();
+
+    let mut builder = MeshBuilder::new();
+    for y in 0..cells {
+        for z in 0..cells {
+            for x in 0..cells {
+                let cell = ivec3(x, y, z);
+                let Some(block_type) = cell_at(cell) else {
+                    continue;
+                };
+                for side in BlockSides::ALL {
+                    let normal = side.normal().as_ivec3();
+                    let next = cell + normal;
+                    let inside =
+                        next.cmpge(IVec3::ZERO).all() && next.cmplt(IVec3::splat(cells)).all();
+                    let visible = if inside {
+                        cell_at(next).is_none_or(|next| registry.is_transparent(next))
+                    } else {
+                        // The layer of blocks just past this face.
+                        let start = origin
+                            + cell * size
+                            + normal.max(IVec3::ZERO) * size
+                            + normal.min(IVec3::ZERO);
+                        let extent =
+                            IVec3::splat(size) * (IVec3::ONE - normal.abs()) + normal.abs();
+                        let mut layer = (0..extent.x).flat_map(|dx| {
+                            (0..extent.y)
+                                .flat_map(move |dy| (0..extent.z).map(move |dz| ivec3(dx, dy, dz)))
+                        });
+                        layer.any(|offset| is_air_outside(start + offset))
+                    };
+                    if visible {
+                        let pos = (origin + cell * size).as_vec3();
+                        let tile = registry.face(block_type, side);
+                        mesh_side(
+                            builder.face(),
+                            side,
+                            pos,
+                            Vec3::splat(size as f32),
+                            tile,
+                            NO_AO,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{
+        meshing::{build_chunk_mesh, MeshingMode},
+        registry::test_registry,
+        terrain::{TerrainBlocks, TerrainGenerator},
+    };
+
+    const STONE: BlockType = BlockType(1);
+    const DIRT: BlockType = BlockType(2);
+    const GRASS: BlockType = BlockType(3);
+
+    fn counts(meshes: &[BlockMesh]) -> (usize, usize) {
+        meshes.iter().fold((0, 0), |(vertices, indices), mesh| {
+            (vertices + mesh.vertices.len(), indices + mesh.indices.len())
+        })
+    }
+
+    fn stone_chunk() -> Chunk {
+        let mut chunk = Chunk::new(ivec3(1, -1, 2));
+        chunk.populate(STONE);
+        chunk
+    }
+
+    #[test]
+    fn levels_follow_the_distances() {
+        let settings = LodSettings::spaced(4);
+        assert_eq!(settings.level(IVec3::ZERO), 0);
+        assert_eq!(settings.level(ivec3(4, 0, 0)), 0);
+        assert_eq!(settings.level(ivec3(3, 0, 3)), 1);
+        // Height does not count.
+        assert_eq!(settings.level(ivec3(0, 9, -5)), 1);
+        assert_eq!(settings.level(ivec3(-9, 0, 0)), 2);
+        assert_eq!(settings.level(ivec3(12, 0, 12)), MAX_LOD);
+        assert_eq!(settings.level(ivec3(100, 0, 0)), MAX_LOD);
+    }
+
+    #[test]
+    fn full_chunk_has_one_quad_per_border_cell_face() {
+        let registry = test_registry();
+        let chunk = stone_chunk();
+        let full = counts(&build_chunk_mesh(
+            &chunk,
+            MeshingMode::Naive,
+            &registry,
+            &|_| true,
+        ));
+        assert_eq!(full, (6 * 256 * 4, 6 * 256 * 6));
+        for (level, cells) in [(1, 8), (2, 4), (3, 2)] {
+            let faces = 6 * cells * cells;
+            assert_eq!(
+                counts(&build_lod_mesh(&chunk, level, &registry, &|_| true)),
+                (faces * 4, faces * 6),
+                "level {level}"
+            );
+        }
+    }
+
+    #[test]
+    fn buried_chunk_has_no_faces() {
+        let registry = test_registry();
+        for level in 1..=MAX_LOD {
+            assert!(build_lod_mesh(&stone_chunk(), level, &registry, &|_| false).is_empty());
+        }
+    }
+
+    #[test]
+    fn one_open_block_behind_the_border_shows_the_cell_face() {
+        let registry = test_registry();
+        let chunk = stone_chunk();
+        let origin = chunk.origin();
+        // A hole right above local (5, 15, 2): in the top layer of cell (1, 3, 0)
+        // at level 2.
+        let hole = origin + ivec3(5, 16, 2);
+        let meshes = build_lod_mesh(&chunk, 2, &registry, &|pos| pos == hole);
+        assert_eq!(counts(&meshes), (4, 6));
+        let top = (origin + ivec3(4, 16, 0)).as_vec3();
+        let corners: Vec<Vec3> = meshes[0].vertices.iter().map(|v| v.position).collect();
+        assert_eq!(
+            corners,
+            [
+                top,
+                top + vec3(4.0, 0.0, 0.0),
+                top + vec3(4.0, 0.0, 4.0),
+                top + vec3(0.0, 0.0, 4.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_single_block_fills_its_whole_cell() {
+        let registry = test_registry();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(5, 9, 14, DIRT);
+        let meshes = build_lod_mesh(&chunk, 3, &registry, &|_| true);
+        assert_eq!(counts(&meshes), (6 * 4, 6 * 6));
+        let (min, max) = meshes[0].vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| (min.min(v.position), max.max(v.position)),
+        );
+        assert_eq!((min, max), (vec3(0.0, 8.0, 8.0), vec3(8.0, 16.0, 16.0)));
+    }
+
+    #[test]
+    fn cells_show_the_block_on_top() {
+        let registry = test_registry();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for z in 0..CHUNK_SIZE_16 {
+            for x in 0..CHUNK_SIZE_16 {
+                for y in 0..10 {
+                    chunk.set(x, y, z, DIRT);
+                }
+                chunk.set(x, 10, z, GRASS);
+            }
+        }
+        let cells = downsample(&chunk, 1, &registry);
+        let at = |x: i32, y: i32, z: i32| cells[((y * 8 + z) * 8 + x) as usize];
+        assert_eq!(at(3, 4, 3), Some(DIRT));
+        assert_eq!(at(3, 5, 3), Some(GRASS));
+        assert_eq!(at(3, 6, 3), None);
+        // Grass over dirt, with the grass taking up only one of four columns.
+        chunk.set(0, 11, 0, GRASS);
+        chunk.set(1, 11, 0, DIRT);
+        chunk.set(0, 11, 1, DIRT);
+        chunk.set(1, 11, 1, DIRT);
+        let cells = downsample(&chunk, 1, &registry);
+        assert_eq!(cells[(5 * 8 * 8) as usize], Some(DIRT));
+    }
+
+    #[test]
+    fn coarse_cells_cover_every_drawn_block() {
+        // What keeps the seams between levels closed: a neighbour culls its
+        // faces against full resolution blocks, and every one of those lies in
+        // a filled cell at every level.
+        let registry = test_registry();
+        let generator = TerrainGenerator::new(9, TerrainBlocks::from_registry(&registry).unwrap())
+            .with_biomes();
+        for coord in [ivec3(0, 2, 0), ivec3(-3, 2, 5), ivec3(7, 1, -2)] {
+            let chunk = generator.generate(coord);
+            for level in 1..=MAX_LOD {
+                let size = cell_size(level);
+                let cells = downsample(&chunk, level, &registry);
+                let n = CHUNK_SIZE_16 / size;
+                for y in 0..CHUNK_SIZE_16 {
+                    for z in 0..CHUNK_SIZE_16 {
+                        for x in 0..CHUNK_SIZE_16 {
+                            if registry.is_drawn(chunk.get(x, y, z)) {
+                                let cell = ((y / size * n + z / size) * n + x / size) as usize;
+                                assert!(cells[cell].is_some(), "{coord} level {level}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,650 @@
+use crate::{
+    systems::{
+        blocks::{BlockMesh, BlockSides, BlockType, RenderSides},
+        chunks::Chunk,
+        lod::build_lod_mesh,
+        registry::BlockRegistry,
+        render_utils::{face_corners, mesh_side, MAX_MESH_VERTICES},
+        visibility::FaceConnections,
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+/// How chunk faces are turned into quads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// One quad per visible block face.
+    Naive,
+    /// Coplanar neighbouring faces of the same block type and ambient occlusion
+    /// are merged into larger quads with the texture tiled across them.
+    Greedy,
+}
+
+/// Collects quads into as few sub-meshes as the `u16` index range allows.
+pub struct MeshBuilder {
+    meshes: Vec<BlockMesh>,
+    vertex_limit: usize,
+}
+
+impl MeshBuilder {
+    pub fn new() -> Self {
+        Self::with_vertex_limit(MAX_MESH_VERTICES)
+    }
+
+    fn with_vertex_limit(vertex_limit: usize) -> Self {
+        Self {
+            meshes: vec![BlockMesh::new()],
+            vertex_limit,
+        }
+    }
+
+    /// Sub-mesh with room for one more quad.
+    pub fn face(&mut self) -> &mut BlockMesh {
+        let full = self.meshes.last().unwrap().vertices.len() + 4 > self.vertex_limit;
+        if full {
+            self.meshes.push(BlockMesh::new());
+        }
+        self.meshes.last_mut().unwrap()
+    }
+
+    /// Appends one block face.
+    pub fn push_face(
+        &mut self,
+        pos: Vec3,
+        side: BlockSides,
+        block_type: BlockType,
+        ao: [u8; 4],
+        registry: &BlockRegistry,
+    ) {
+        let tile = registry.face(block_type, side);
+        mesh_side(self.face(), side, pos, Vec3::ONE, tile, ao);
+    }
+
+    pub fn finish(self) -> Vec<BlockMesh> {
+        self.meshes
+            .into_iter()
+            .filter(|mesh| !mesh.is_empty())
+            .collect()
+    }
+}
+
+/// Occlusion level of a vertex, from the two blocks that share an edge with it
+/// and the one diagonal to it, all in the layer in front of the face. Two solid
+/// edge blocks fully occlude the corner whatever the diagonal is.
+pub fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - side1 as u8 - side2 as u8 - corner as u8
+    }
+}
+
+/// Ambient occlusion of the corners of `side` of the block at local `pos`, in
+/// the order `face_corners` emits them.
+fn face_ao(
+    chunk: &Chunk,
+    registry: &BlockRegistry,
+    is_air_outside: &impl Fn(IVec3) -> bool,
+    pos: IVec3,
+    side: BlockSides,
+) -> [u8; 4] {
+    let normal = side.normal().as_ivec3();
+    let front = pos + normal;
+    let solid = |offset: IVec3| !chunk.is_air_at(front + offset, registry, is_air_outside);
+    face_corners(side).map(|corner| {
+        // Step from the face centre towards the corner along each in-plane axis.
+        let step = (corner * 2.0 - Vec3::ONE).as_ivec3();
+        let mut edges = (0..3).filter(|&axis| normal[axis] == 0).map(|axis| {
+            let mut edge = IVec3::ZERO;
+            edge[axis] = step[axis];
+            edge
+        });
+        let (a, b) = (edges.next().unwrap(), edges.next().unwrap());
+        vertex_ao(solid(a), solid(b), solid(a + b))
+    })
+}
+
+/// Side of the cube of blocks a `MeshInput` covers: the chunk and a one block
+/// shell around it.
+const SHELL: i32 = CHUNK_SIZE_16 + 2;
+
+fn shell_index(local: IVec3) -> usize {
+    let pos = local + IVec3::ONE;
+    ((pos.y * SHELL + pos.z) * SHELL + pos.x) as usize
+}
+
+/// A copy of everything `build_chunk_mesh` reads, so a chunk can be meshed away
+/// from the world: its blocks, and which blocks of the one block thick shell
+/// around it are transparent.
+pub struct MeshInput {
+    chunk: Chunk,
+    shell: Vec<bool>,
+}
+
+impl MeshInput {
+    pub fn new(chunk: &Chunk, is_air_outside: impl Fn(IVec3) -> bool) -> Self {
+        let origin = chunk.origin();
+        let mut shell = vec![false; (SHELL * SHELL * SHELL) as usize];
+        for y in -1..=CHUNK_SIZE_16 {
+            for z in -1..=CHUNK_SIZE_16 {
+                for x in -1..=CHUNK_SIZE_16 {
+                    let local = ivec3(x, y, z);
+                    let inside = local.cmpge(IVec3::ZERO).all()
+                        && local.cmplt(IVec3::splat(CHUNK_SIZE_16)).all();
+                    if !inside {
+                        shell[shell_index(local)] = is_air_outside(origin + local);
+                    }
+                }
+            }
+        }
+        Self {
+            chunk: chunk.snapshot(),
+            shell,
+        }
+    }
+
+    pub fn coord(&self) -> IVec3 {
+        self.chunk.position()
+    }
+
+    /// Which faces of the chunk see each other, for occlusion culling.
+    pub fn connections(&self, registry: &BlockRegistry) -> FaceConnections {
+        FaceConnections::compute(&self.chunk, registry)
+    }
+
+    /// Meshes the chunk at its level of detail. `mode` only applies at full
+    /// resolution.
+    pub fn build(&self, mode: MeshingMode, registry: &BlockRegistry) -> Vec<BlockMesh> {
+        let origin = self.chunk.origin();
+        let is_air_outside = |pos: IVec3| self.shell[shell_index(pos - origin)];
+        match self.chunk.lod() {
+            0 => build_chunk_mesh(&self.chunk, mode, registry, &is_air_outside),
+            level => build_lod_mesh(&self.chunk, level, registry, &is_air_outside),
+        }
+    }
+}
+
+/// Builds the combined vertex and index buffers for every visible face in
+/// `chunk`, in world space.
+pub fn build_chunk_mesh(
+    chunk: &Chunk,
+    mode: MeshingMode,
+    registry: &BlockRegistry,
+    is_air_outside: &impl Fn(IVec3) -> bool,
+) -> Vec<BlockMesh> {
+    match mode {
+        MeshingMode::Naive => naive_mesh(chunk, registry, is_air_outside),
+        MeshingMode::Greedy => greedy_mesh(chunk, registry, is_air_outside),
+    }
+}
+
+fn naive_mesh(
+    chunk: &Chunk,
+    registry: &BlockRegistry,
+    is_air_outside: &impl Fn(IVec3) -> bool,
+) -> Vec<BlockMesh> {
+    let mut builder = MeshBuilder::new();
+    let origin = chunk.origin().as_vec3();
+    for y in 0..CHUNK_SIZE_16 {
+        for x in 0..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                let block_type = chunk.get(x, y, z);
+                if !registry.is_drawn(block_type) {
+                    continue;
+                }
+                let sides = chunk.visible_sides(x, y, z, registry, is_air_outside);
+                let pos = origin + vec3(x as f32, y as f32, z as f32);
+                for side in BlockSides::ALL {
+                    if sides.get(side) {
+                        let ao = face_ao(chunk, registry, is_air_outside, ivec3(x, y, z), side);
+                        builder.push_face(pos, side, block_type, ao, registry);
+                    }
+                }
+            }
+        }
+    }
+    builder.finish()
+}
+
+/// Maps (slice, u, v) on the plane of `side` back to local block coordinates.
+fn slice_to_local(side: BlockSides, slice: i32, u: i32, v: i32) -> IVec3 {
+    match side {
+        BlockSides::Top | BlockSides::Bottom => ivec3(u, slice, v),
+        BlockSides::Front | BlockSides::Back => ivec3(u, v, slice),
+        BlockSides::Left | BlockSides::Right => ivec3(slice, v, u),
+    }
+}
+
+fn greedy_mesh(
+    chunk: &Chunk,
+    registry: &BlockRegistry,
+    is_air_outside: &impl Fn(IVec3) -> bool,
+) -> Vec<BlockMesh> {
+    const SIZE: usize = CHUNK_SIZE_16 as usize;
+    let mut builder = MeshBuilder::new();
+    let origin = chunk.origin().as_vec3();
+
+    let mut sides = Vec::with_capacity(SIZE * SIZE * SIZE);
+    for y in 0..CHUNK_SIZE_16 {
+        for z in 0..CHUNK_SIZE_16 {
+            for x in 0..CHUNK_SIZE_16 {
+                sides.push(if registry.is_drawn(chunk.get(x, y, z)) {
+                    chunk.visible_sides(x, y, z, registry, is_air_outside)
+                } else {
+                    RenderSides::default()
+                });
+            }
+        }
+    }
+    let face_at = |side: BlockSides, pos: IVec3| -> Option<(BlockType, [u8; 4])> {
+        let index = ((pos.y * CHUNK_SIZE_16 + pos.z) * CHUNK_SIZE_16 + pos.x) as usize;
+        sides[index].get(side).then(|| {
+            let ao = face_ao(chunk, registry, is_air_outside, pos, side);
+            (chunk.get(pos.x, pos.y, pos.z), ao)
+        })
+    };
+
+    for side in BlockSides::ALL {
+        for slice in 0..CHUNK_SIZE_16 {
+            let mut mask = [[None; SIZE]; SIZE];
+            for (v, row) in mask.iter_mut().enumerate() {
+                for (u, cell) in row.iter_mut().enumerate() {
+                    *cell = face_at(side, slice_to_local(side, slice, u as i32, v as i32));
+                }
+            }
+
+            for v in 0..SIZE {
+                let mut u = 0;
+                while u < SIZE {
+                    let Some(face) = mask[v][u] else {
+                        u += 1;
+                        continue;
+                    };
+                    let mut width = 1;
+                    while u + width < SIZE && mask[v][u + width] == Some(face) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while v + height < SIZE
+                        && mask[v + height][u..u + width]
+                            .iter()
+                            .all(|cell| *cell == Some(face))
+                    {
+                        height += 1;
+                    }
+                    for row in &mut mask[v..v + height] {
+                        row[u..u + width].fill(None);
+                    }
+
+                    let local = slice_to_local(side, slice, u as i32, v as i32);
+                    let extent = slice_to_local(side, 1, width as i32, height as i32);
+                    mesh_side(
+                        builder.face(),
+                        side,
+                        origin + local.as_vec3(),
+                        extent.as_vec3(),
+                        registry.face(face.0, side),
+                        face.1,
+                    );
+                    u += width;
+                }
+            }
+        }
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{
+        atlas::{AtlasLayout, DEFAULT_ATLAS_TILES},
+        registry::test_registry,
+        render_utils::NO_AO,
+    };
+
+    // Ids from the shipped `assets/blocks.toml`.
+    const STONE: BlockType = BlockType(1);
+    const DIRT: BlockType = BlockType(2);
+    const GRASS: BlockType = BlockType(3);
+
+    fn counts(meshes: &[BlockMesh]) -> (usize, usize) {
+        let vertices = meshes.iter().map(|mesh| mesh.vertices.len()).sum();
+        let faces = meshes.iter().map(|mesh| mesh.indices.len()).sum::<usize>() / 6;
+        (vertices, faces)
+    }
+
+    fn atlas() -> AtlasLayout {
+        AtlasLayout::new(1, DEFAULT_ATLAS_TILES.len() as u32, &DEFAULT_ATLAS_TILES)
+    }
+
+    const AIR: fn(IVec3) -> bool = |_| true;
+    const SOLID: fn(IVec3) -> bool = |_| false;
+
+    #[test]
+    fn empty_chunk_has_no_meshes() {
+        let chunk = Chunk::new(IVec3::ZERO);
+        assert!(build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR).is_empty());
+    }
+
+    #[test]
+    fn mesh_input_meshes_like_the_world() {
+        let registry = test_registry();
+        let mut chunk = Chunk::new(ivec3(-1, 2, 0));
+        chunk.fill_where(STONE, |x, y, z| (x + y * 3 + z * 5) % 4 != 0);
+        // A checkered neighbourhood, so every border face and AO corner counts.
+        let outside = |pos: IVec3| (pos.x + pos.y + pos.z) % 2 == 0;
+        let input = MeshInput::new(&chunk, outside);
+        assert_eq!(input.coord(), ivec3(-1, 2, 0));
+        for mode in [MeshingMode::Naive, MeshingMode::Greedy] {
+            let expected = build_chunk_mesh(&chunk, mode, &registry, &outside);
+            assert_eq!(
+                format!("{:?}", input.build(mode, &registry)),
+                format!("{expected:?}")
+            );
+        }
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(4, 4, 4, STONE);
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(counts(&meshes), (24, 6));
+        assert_eq!(meshes[0].indices.len(), 36);
+    }
+
+    #[test]
+    fn adjacent_blocks_hide_shared_faces() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(4, 4, 4, STONE);
+        chunk.set(5, 4, 4, STONE);
+        chunk.set(5, 5, 4, STONE);
+        assert_eq!(
+            counts(&build_chunk_mesh(
+                &chunk,
+                MeshingMode::Naive,
+                &test_registry(),
+                &AIR
+            )),
+            (14 * 4, 14)
+        );
+    }
+
+    #[test]
+    fn full_chunk_only_meshes_its_shell() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.populate(STONE);
+        let shell = (6 * CHUNK_SIZE_16 * CHUNK_SIZE_16) as usize;
+        assert_eq!(
+            counts(&build_chunk_mesh(
+                &chunk,
+                MeshingMode::Naive,
+                &test_registry(),
+                &AIR
+            )),
+            (shell * 4, shell)
+        );
+        assert!(build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &SOLID).is_empty());
+    }
+
+    #[test]
+    fn checkerboard_fits_in_one_mesh() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |x, y, z| (x + y + z) % 2 == 0);
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(counts(&meshes), (2048 * 24, 2048 * 6));
+    }
+
+    #[test]
+    fn meshes_are_in_world_space() {
+        let mut chunk = Chunk::new(ivec3(-1, 0, 2));
+        chunk.set(0, 0, 0, STONE);
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        let min = meshes[0]
+            .vertices
+            .iter()
+            .fold(Vec3::MAX, |min, vertex| min.min(vertex.position));
+        assert_eq!(min, vec3(-16.0, 0.0, 32.0));
+    }
+
+    #[test]
+    fn builder_splits_at_the_vertex_limit() {
+        let mut builder = MeshBuilder::with_vertex_limit(10);
+        for side in BlockSides::ALL {
+            builder.push_face(Vec3::ZERO, side, STONE, NO_AO, &test_registry());
+        }
+        let meshes = builder.finish();
+        assert_eq!(
+            meshes
+                .iter()
+                .map(|mesh| mesh.vertices.len())
+                .collect::<Vec<_>>(),
+            vec![8, 8, 8]
+        );
+        assert!(meshes.iter().all(|mesh| mesh
+            .indices
+            .iter()
+            .all(|&i| (i as usize) < mesh.vertices.len())));
+    }
+
+    /// Sum of quad areas in block faces, which both modes must agree on.
+    fn area(meshes: &[BlockMesh]) -> f32 {
+        meshes
+            .iter()
+            .flat_map(|mesh| mesh.vertices.chunks(4))
+            .map(|quad| {
+                let a = quad[0].position;
+                (quad[1].position - a).cross(quad[3].position - a).length()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn greedy_merges_a_flat_layer_into_six_quads() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |_, y, _| y == 0);
+        let naive = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        let greedy = build_chunk_mesh(&chunk, MeshingMode::Greedy, &test_registry(), &AIR);
+        assert_eq!(counts(&naive).1, 2 * 256 + 4 * 16);
+        assert_eq!(counts(&greedy), (6 * 4, 6));
+        assert_eq!(area(&greedy), area(&naive));
+    }
+
+    #[test]
+    fn greedy_tiles_uvs_across_merged_quads() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |x, y, z| y == 0 && x < 4 && z < 3);
+        let greedy = build_chunk_mesh(&chunk, MeshingMode::Greedy, &test_registry(), &AIR);
+        let top = greedy[0]
+            .vertices
+            .chunks(4)
+            .find(|quad| quad.iter().all(|vertex| vertex.position.y == 1.0))
+            .unwrap();
+        let stone = atlas().tile("stone").unwrap();
+        let max_uv = top
+            .iter()
+            .fold(Vec2::ZERO, |max, vertex| max.max(vertex.uv));
+        assert_eq!(max_uv, stone.uv(vec2(4.0, 3.0)));
+        assert!(top
+            .iter()
+            .all(|vertex| vertex.normal.w == stone.index as f32));
+    }
+
+    #[test]
+    fn greedy_keeps_block_types_apart() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                let block_type = if x < 8 { STONE } else { GRASS };
+                chunk.set(x, 0, z, block_type);
+            }
+        }
+        let greedy = build_chunk_mesh(&chunk, MeshingMode::Greedy, &test_registry(), &AIR);
+        // Top and bottom split in two, front and back split in two, left and right whole.
+        assert_eq!(counts(&greedy).1, 2 * 2 + 2 * 2 + 2);
+    }
+
+    #[test]
+    fn greedy_covers_the_same_area_as_naive() {
+        let mut chunk = Chunk::new(ivec3(2, -1, 3));
+        chunk.fill_where(STONE, |x, y, z| {
+            ((x as f32).cos() + (y as f32).tan() * (z as f32).sin()).sin() > 0.0
+        });
+        for solid_outside in [false, true] {
+            let outside = |pos: IVec3| !solid_outside || pos.y < -16;
+            let naive = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &outside);
+            let greedy = build_chunk_mesh(&chunk, MeshingMode::Greedy, &test_registry(), &outside);
+            assert_eq!(area(&greedy), area(&naive));
+            assert!(counts(&greedy).1 < counts(&naive).1);
+        }
+    }
+
+    #[test]
+    fn faces_use_atlas_uvs() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(0, 0, 0, DIRT);
+        chunk.set(2, 0, 0, STONE);
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        for vertex in &meshes[0].vertices {
+            let tile_name = if vertex.position.x < 2.0 {
+                "dirt"
+            } else {
+                "stone"
+            };
+            let rect = atlas().tile(tile_name).unwrap().rect;
+            assert!((rect.x..=rect.right()).contains(&vertex.uv.x));
+            assert!((rect.y..=rect.bottom()).contains(&vertex.uv.y));
+        }
+    }
+
+    #[test]
+    fn transparent_neighbours_keep_faces_visible() {
+        let registry = BlockRegistry::from_toml(
+            "[[block]]\nid = 0\nname = \"air\"\nsolid = false\ntransparent = true\n\
+             [[block]]\nid = 1\nname = \"stone\"\ntextures = { all = \"stone\" }\n\
+             [[block]]\nid = 2\nname = \"glass\"\ntransparent = true\n\
+             textures = { all = \"dirt\" }\n",
+            &atlas(),
+        )
+        .unwrap();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(4, 4, 4, STONE);
+        chunk.set(5, 4, 4, BlockType(2));
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &registry, &AIR);
+        // The stone face behind the glass stays, the glass face against the stone goes.
+        assert_eq!(counts(&meshes).1, 6 + 5);
+    }
+
+    #[test]
+    fn vertex_ao_counts_occluders() {
+        assert_eq!(vertex_ao(false, false, false), 3);
+        assert_eq!(vertex_ao(true, false, false), 2);
+        assert_eq!(vertex_ao(false, false, true), 2);
+        assert_eq!(vertex_ao(false, true, true), 1);
+        assert_eq!(vertex_ao(true, true, false), 0);
+        assert_eq!(vertex_ao(true, true, true), 0);
+    }
+
+    /// AO of the top face of the floor block at (5, 0, 5) with `blocks` on top of
+    /// the floor.
+    fn floor_ao(blocks: &[IVec3]) -> [u8; 4] {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |_, y, _| y == 0);
+        for pos in blocks {
+            chunk.set(pos.x, pos.y, pos.z, STONE);
+        }
+        face_ao(
+            &chunk,
+            &test_registry(),
+            &AIR,
+            ivec3(5, 0, 5),
+            BlockSides::Top,
+        )
+    }
+
+    #[test]
+    fn ao_on_small_arrangements() {
+        // Corners in `face_corners` order: (0, 0), (1, 0), (1, 1), (0, 1) in x, z.
+        assert_eq!(floor_ao(&[]), [3, 3, 3, 3]);
+        // A wall along -x darkens both corners on that edge.
+        assert_eq!(floor_ao(&[ivec3(4, 1, 5)]), [2, 3, 3, 2]);
+        // A block touching only the -x -z corner.
+        assert_eq!(floor_ao(&[ivec3(4, 1, 4)]), [2, 3, 3, 3]);
+        // An inside corner is fully occluded.
+        assert_eq!(floor_ao(&[ivec3(4, 1, 5), ivec3(5, 1, 4)]), [0, 2, 3, 2]);
+        // Walls on opposite sides.
+        assert_eq!(floor_ao(&[ivec3(4, 1, 5), ivec3(6, 1, 5)]), [2, 2, 2, 2]);
+        // Blocks below the face do not occlude it.
+        assert_eq!(floor_ao(&[ivec3(4, 0, 4)]), [3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn ao_looks_across_chunk_borders() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(0, 0, 0, STONE);
+        let wall_west = |pos: IVec3| !(pos.x == -1 && pos.y == 1);
+        let ao = face_ao(
+            &chunk,
+            &test_registry(),
+            &wall_west,
+            IVec3::ZERO,
+            BlockSides::Top,
+        );
+        // The wall runs along z, so the diagonal neighbours are solid as well.
+        assert_eq!(ao, [1, 3, 3, 1]);
+    }
+
+    #[test]
+    fn occluded_vertices_are_darker() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set(5, 0, 5, STONE);
+        chunk.set(4, 1, 5, STONE);
+        let meshes = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        let top = meshes[0]
+            .vertices
+            .chunks(4)
+            .find(|quad| {
+                quad.iter()
+                    .all(|vertex| vertex.position.y == 1.0 && vertex.position.x >= 5.0)
+            })
+            .unwrap();
+        for vertex in top {
+            if vertex.position.x == 5.0 {
+                assert!(vertex.color[0] < 255);
+            } else {
+                assert_eq!(vertex.color[0], 255);
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_only_merges_faces_with_matching_ao() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, |_, y, _| y == 0);
+        chunk.set(8, 1, 8, STONE);
+        let naive = build_chunk_mesh(&chunk, MeshingMode::Naive, &test_registry(), &AIR);
+        let greedy = build_chunk_mesh(&chunk, MeshingMode::Greedy, &test_registry(), &AIR);
+        assert_eq!(area(&greedy), area(&naive));
+
+        // The eight floor faces around the pillar each keep their own shading.
+        let shaded: Vec<_> = greedy
+            .iter()
+            .flat_map(|mesh| mesh.vertices.chunks(4))
+            .filter(|quad| {
+                quad.iter().all(|vertex| vertex.position.y == 1.0)
+                    && quad.iter().any(|vertex| vertex.color[0] < 255)
+            })
+            .collect();
+        assert_eq!(shaded.len(), 8);
+        assert!(shaded
+            .iter()
+            .all(|quad| (quad[1].position - quad[0].position)
+                .cross(quad[3].position - quad[0].position)
+                .length()
+                == 1.0));
+    }
+}
@@ -1,6 +1,26 @@
-
-pub(crate) mod controls;
+pub(crate) mod aabb;
+pub(crate) mod atlas;
+pub(crate) mod biomes;
+pub(crate) mod blocks;
+pub(crate) mod caves;
 pub(crate) mod chunks;
+pub(crate) mod controls;
+pub(crate) mod editing;
+pub(crate) mod frustum;
+pub(crate) mod hud;
+pub(crate) mod lod;
+pub(crate) mod meshing;
+pub(crate) mod noise;
+pub(crate) mod ores;
+pub(crate) mod palette;
+pub(crate) mod physics;
+pub(crate) mod raycast;
+pub(crate) mod registry;
 pub(crate) mod render_utils;
-pub(crate) mod demo_features;
-pub(crate) mod blocks;
\ No newline at end of file
+pub(crate) mod streaming;
+pub(crate) mod structures;
+pub(crate) mod terrain;
+pub(crate) mod timestep;
+pub(crate) mod visibility;
+pub(crate) mod workers;
+pub(crate) mod world;
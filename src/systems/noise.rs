@@ -0,0 +1,248 @@
+use macroquad::prelude::*;
+
+/// Next value of the SplitMix64 sequence. Small, fast and fully specified, so
+/// seeded generation never depends on a library's choice of RNG.
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Seed for the random choices a generation pass makes in one chunk, mixed from
+/// the world seed, the chunk coordinate and a `salt` naming the pass.
+pub fn chunk_seed(seed: u64, coord: IVec3, salt: u64) -> u64 {
+    let mut state = seed ^ salt;
+    for axis in coord.to_array() {
+        state = splitmix64(&mut state) ^ axis as u32 as u64;
+    }
+    splitmix64(&mut state)
+}
+
+/// Uniform pick from `0..n`, advancing `state`.
+pub fn below(state: &mut u64, n: u32) -> i32 {
+    (splitmix64(state) % n as u64) as i32
+}
+
+/// Uniform pick from `0.0..1.0`, advancing `state`.
+pub fn chance(state: &mut u64) -> f64 {
+    (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Seeded 2D and 3D gradient (Perlin) noise in roughly -1..1.
+///
+/// Only basic IEEE arithmetic is used, so a seed gives bit-identical values on
+/// every platform.
+#[derive(Clone, Debug)]
+pub struct GradientNoise {
+    perm: [u8; 512],
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn grad(hash: u8, x: f64, z: f64) -> f64 {
+    match hash & 7 {
+        0 => x + z,
+        1 => -x + z,
+        2 => x - z,
+        3 => -x - z,
+        4 => x,
+        5 => -x,
+        6 => z,
+        _ => -z,
+    }
+}
+
+/// Dot product with one of the twelve cube edge directions.
+fn grad3(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl GradientNoise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut state = seed;
+        for i in (1..table.len()).rev() {
+            let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+        Self {
+            perm: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    pub fn sample(&self, x: f64, z: f64) -> f64 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let xi = (x0 as i64 & 255) as usize;
+        let zi = (z0 as i64 & 255) as usize;
+
+        let hash = |dx: usize, dz: usize| self.perm[self.perm[xi + dx] as usize + zi + dz];
+        let (u, v) = (fade(fx), fade(fz));
+        lerp(
+            lerp(grad(hash(0, 0), fx, fz), grad(hash(1, 0), fx - 1.0, fz), u),
+            lerp(
+                grad(hash(0, 1), fx, fz - 1.0),
+                grad(hash(1, 1), fx - 1.0, fz - 1.0),
+                u,
+            ),
+            v,
+        )
+    }
+
+    pub fn sample3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+        let xi = (x0 as i64 & 255) as usize;
+        let yi = (y0 as i64 & 255) as usize;
+        let zi = (z0 as i64 & 255) as usize;
+
+        let hash = |dx: usize, dy: usize, dz: usize| {
+            let xy = self.perm[self.perm[xi + dx] as usize + yi + dy] as usize;
+            self.perm[xy + zi + dz]
+        };
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            grad3(
+                hash(dx, dy, dz),
+                fx - dx as f64,
+                fy - dy as f64,
+                fz - dz as f64,
+            )
+        };
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+}
+
+/// Fractal Brownian motion: `octaves` layers of gradient noise, each at
+/// `lacunarity` times the frequency and `gain` times the amplitude of the one
+/// before. The sum is normalised back into roughly -1..1.
+#[derive(Clone, Copy, Debug)]
+pub struct Fbm {
+    pub octaves: u32,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Fbm {
+    pub fn sample(&self, noise: &GradientNoise, x: f64, z: f64) -> f64 {
+        let (mut sum, mut total) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (self.frequency, 1.0);
+        for octave in 0..self.octaves {
+            // Shift every octave so their lattice points, where gradient noise is
+            // always zero, do not line up.
+            let shift = octave as f64 * 31.7;
+            sum += amplitude * noise.sample(x * frequency + shift, z * frequency - shift);
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        sum / total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix_matches_reference_values() {
+        let mut state = 0;
+        assert_eq!(splitmix64(&mut state), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(splitmix64(&mut state), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn chunk_seeds_differ_by_coordinate_and_salt() {
+        let seed = chunk_seed(5, ivec3(1, 2, 3), 0);
+        assert_eq!(seed, chunk_seed(5, ivec3(1, 2, 3), 0));
+        for other in [
+            chunk_seed(6, ivec3(1, 2, 3), 0),
+            chunk_seed(5, ivec3(2, 1, 3), 0),
+            chunk_seed(5, ivec3(1, 2, -3), 0),
+            chunk_seed(5, ivec3(1, 2, 3), 1),
+        ] {
+            assert_ne!(seed, other);
+        }
+    }
+
+    #[test]
+    fn noise_is_zero_on_the_lattice_and_bounded() {
+        let noise = GradientNoise::new(7);
+        assert_eq!(noise.sample(3.0, -5.0), 0.0);
+        for i in 0..2000 {
+            let (x, z) = (i as f64 * 0.137 - 40.0, i as f64 * 0.291 - 90.0);
+            assert!(noise.sample(x, z).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn noise_3d_is_zero_on_the_lattice_and_bounded() {
+        let noise = GradientNoise::new(11);
+        assert_eq!(noise.sample3(3.0, -5.0, 8.0), 0.0);
+        for i in 0..4000 {
+            let f = i as f64;
+            let value = noise.sample3(f * 0.137 - 40.0, f * 0.071 + 3.0, f * -0.291);
+            assert!(value.abs() <= 1.1);
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let noise = GradientNoise::new(99);
+        for i in 0..500 {
+            let x = i as f64 * 0.05 - 12.0;
+            let z = i as f64 * 0.03 + 4.0;
+            assert!((noise.sample(x, z) - noise.sample(x + 1e-4, z)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn seeds_are_deterministic_and_distinct() {
+        let a = GradientNoise::new(1);
+        let b = GradientNoise::new(1);
+        let c = GradientNoise::new(2);
+        let fbm = Fbm {
+            octaves: 4,
+            frequency: 0.05,
+            lacunarity: 2.0,
+            gain: 0.5,
+        };
+        let sample = |noise: &GradientNoise| {
+            (0..64)
+                .map(|i| fbm.sample(noise, i as f64 * 3.3, i as f64 * -1.7))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sample(&a), sample(&b));
+        assert_ne!(sample(&a), sample(&c));
+    }
+}
@@ -0,0 +1,236 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    systems::{
+        blocks::BlockType,
+        chunks::Chunk,
+        noise::{below, chunk_seed},
+        registry::{BlockRegistry, RegistryError},
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+/// Salt of the ore pass in `chunk_seed`, offset by the index of each ore.
+const ORE_SALT: u64 = 0x6F72_6573;
+
+const NEIGHBOURS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// One kind of ore and where its veins form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OreSettings {
+    pub block: BlockType,
+    /// Blocks in a vein, unless it runs into the chunk border or out of host
+    /// blocks first.
+    pub vein_size: u32,
+    /// Veins started in every chunk the height range reaches into.
+    pub veins_per_chunk: u32,
+    /// World heights the veins stay within.
+    pub height: RangeInclusive<i32>,
+    /// Blocks the ore may replace.
+    pub hosts: Vec<BlockType>,
+}
+
+/// Coal, iron and gold in stone, each rarer and deeper than the one before.
+pub fn default_ores(registry: &BlockRegistry) -> Result<Vec<OreSettings>, RegistryError> {
+    let stone = registry.require("stone")?;
+    let ore = |name, vein_size, veins_per_chunk, height| {
+        Ok(OreSettings {
+            block: registry.require(name)?,
+            vein_size,
+            veins_per_chunk,
+            height,
+            hosts: vec![stone],
+        })
+    };
+    Ok(vec![
+        ore("coal_ore", 12, 6, -64..=96)?,
+        ore("iron_ore", 8, 4, -64..=48)?,
+        ore("gold_ore", 6, 1, -64..=24)?,
+    ])
+}
+
+/// Grows the veins of every ore in `ores` inside `chunk`, in order. Which blocks
+/// turn into ore depends only on the seed, the chunk coordinate and the blocks
+/// already in the chunk, so chunks can be filled in any order.
+pub fn place_ores(chunk: &mut Chunk, seed: u64, ores: &[OreSettings]) {
+    let origin = chunk.origin();
+    for (index, ore) in ores.iter().enumerate() {
+        // Chunk local heights the ore may occupy.
+        let low = (ore.height.start() - origin.y).max(0);
+        let high = (ore.height.end() - origin.y).min(CHUNK_SIZE_16 - 1);
+        if low > high {
+            continue;
+        }
+        let mut state = chunk_seed(seed, chunk.position(), ORE_SALT + index as u64);
+        for _ in 0..ore.veins_per_chunk {
+            let start = ivec3(
+                below(&mut state, CHUNK_SIZE_16 as u32),
+                low + below(&mut state, (high - low + 1) as u32),
+                below(&mut state, CHUNK_SIZE_16 as u32),
+            );
+            grow_vein(chunk, ore, start, low..=high, &mut state);
+        }
+    }
+}
+
+/// Grows a blob of ore from `start`: every step turns a random neighbour of a
+/// random block of the vein into ore, if it is a host block inside the chunk.
+fn grow_vein(
+    chunk: &mut Chunk,
+    ore: &OreSettings,
+    start: IVec3,
+    heights: RangeInclusive<i32>,
+    state: &mut u64,
+) {
+    let mut vein = Vec::with_capacity(ore.vein_size as usize);
+    let mut candidate = start;
+    // Blocked steps are retried, up to a bound so enclosed veins stop.
+    for _ in 0..ore.vein_size * 4 {
+        let inside = candidate.x >= 0
+            && candidate.x < CHUNK_SIZE_16
+            && candidate.z >= 0
+            && candidate.z < CHUNK_SIZE_16
+            && heights.contains(&candidate.y);
+        if inside
+            && ore
+                .hosts
+                .contains(&chunk.get(candidate.x, candidate.y, candidate.z))
+        {
+            chunk.set(candidate.x, candidate.y, candidate.z, ore.block);
+            vein.push(candidate);
+            if vein.len() == ore.vein_size as usize {
+                break;
+            }
+        }
+        if vein.is_empty() {
+            break;
+        }
+        let from = vein[below(state, vein.len() as u32) as usize];
+        candidate = from + NEIGHBOURS[below(state, 6) as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::registry::test_registry;
+
+    const STONE: BlockType = BlockType(1);
+    const DIRT: BlockType = BlockType(2);
+
+    fn ores() -> Vec<OreSettings> {
+        default_ores(&test_registry()).unwrap()
+    }
+
+    fn count(chunk: &Chunk, block_type: BlockType) -> u32 {
+        let mut count = 0;
+        for y in 0..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                for x in 0..CHUNK_SIZE_16 {
+                    count += (chunk.get(x, y, z) == block_type) as u32;
+                }
+            }
+        }
+        count
+    }
+
+    fn stone_chunk(coord: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(coord);
+        chunk.populate(STONE);
+        chunk
+    }
+
+    #[test]
+    fn ore_frequencies_match_the_settings() {
+        let ores = ores();
+        let mut totals = vec![0; ores.len()];
+        let mut chunks = 0;
+        // Chunks with y from -4 to -1 lie inside every default height range.
+        for x in -4..4 {
+            for y in -4..0 {
+                for z in -4..4 {
+                    let mut chunk = stone_chunk(ivec3(x, y, z));
+                    place_ores(&mut chunk, 99, &ores);
+                    for (total, ore) in totals.iter_mut().zip(&ores) {
+                        *total += count(&chunk, ore.block);
+                    }
+                    chunks += 1;
+                }
+            }
+        }
+
+        for (ore, total) in ores.iter().zip(totals) {
+            let per_chunk = total as f64 / chunks as f64;
+            let expected = (ore.vein_size * ore.veins_per_chunk) as f64;
+            // Veins cut short by the chunk border or by each other pull the
+            // average a little below the configured size.
+            assert!(
+                (expected * 0.7..=expected).contains(&per_chunk),
+                "{:?}: {per_chunk} per chunk, configured {expected}",
+                ore.block
+            );
+        }
+    }
+
+    #[test]
+    fn veins_keep_to_their_height_range() {
+        let ores = ores();
+        let gold = &ores[2];
+        for y in -2..6 {
+            let mut chunk = stone_chunk(ivec3(3, y, -7));
+            place_ores(&mut chunk, 4, &ores);
+            for local_y in 0..CHUNK_SIZE_16 {
+                let world_y = y * CHUNK_SIZE_16 + local_y;
+                for z in 0..CHUNK_SIZE_16 {
+                    for x in 0..CHUNK_SIZE_16 {
+                        if chunk.get(x, local_y, z) == gold.block {
+                            assert!(gold.height.contains(&world_y), "gold at y {world_y}");
+                        }
+                    }
+                }
+            }
+        }
+        let mut above = stone_chunk(ivec3(0, 7, 0));
+        place_ores(&mut above, 4, &ores);
+        assert_eq!(count(&above, STONE), 16 * 16 * 16);
+    }
+
+    #[test]
+    fn only_host_blocks_are_replaced() {
+        let mut chunk = stone_chunk(ivec3(0, -1, 0));
+        for y in 8..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                for x in 0..CHUNK_SIZE_16 {
+                    chunk.set(x, y, z, DIRT);
+                }
+            }
+        }
+        place_ores(&mut chunk, 12, &ores());
+        assert_eq!(count(&chunk, DIRT), 16 * 16 * 8);
+        assert!(count(&chunk, STONE) < 16 * 16 * 8);
+    }
+
+    #[test]
+    fn placement_depends_on_seed_and_coordinate() {
+        let ores = ores();
+        let generate = |seed, coord| {
+            let mut chunk = stone_chunk(coord);
+            place_ores(&mut chunk, seed, &ores);
+            (0..CHUNK_SIZE_16.pow(3))
+                .map(|i| chunk.get(i % 16, i / 256, i / 16 % 16))
+                .collect::<Vec<_>>()
+        };
+        let base = generate(1, ivec3(2, -1, 5));
+        assert_eq!(base, generate(1, ivec3(2, -1, 5)));
+        assert_ne!(base, generate(2, ivec3(2, -1, 5)));
+        assert_ne!(base, generate(1, ivec3(5, -1, 2)));
+    }
+}
@@ -0,0 +1,252 @@
+/// Flat, palette-indexed storage for a fixed number of cells.
+///
+/// Every cell holds an index into `palette` packed into `u64` words. The width of
+/// an index grows 1 -> 4 -> 8 -> 16 bits as the palette fills up, and can be
+/// shrunk back with [`PalettedStorage::compact`] once values stop being used.
+#[derive(Clone, Debug)]
+pub struct PalettedStorage<T> {
+    palette: Vec<PaletteEntry<T>>,
+    bits: u32,
+    data: Vec<u64>,
+    len: usize,
+}
+
+#[derive(Clone, Debug)]
+struct PaletteEntry<T> {
+    value: T,
+    refs: usize,
+}
+
+const BIT_WIDTHS: [u32; 4] = [1, 4, 8, 16];
+
+fn width_for(palette_len: usize) -> u32 {
+    BIT_WIDTHS
+        .into_iter()
+        .find(|bits| palette_len <= 1 << bits)
+        .expect("palette cannot hold more than 65536 entries")
+}
+
+fn words_for(len: usize, bits: u32) -> usize {
+    let per_word = (64 / bits) as usize;
+    len.div_ceil(per_word)
+}
+
+impl<T: Copy + PartialEq> PalettedStorage<T> {
+    pub fn new(len: usize, fill: T) -> Self {
+        Self {
+            palette: vec![PaletteEntry {
+                value: fill,
+                refs: len,
+            }],
+            bits: BIT_WIDTHS[0],
+            data: vec![0; words_for(len, BIT_WIDTHS[0])],
+            len,
+        }
+    }
+
+    /// Bits used for every packed palette index.
+    #[cfg(test)]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Number of distinct values currently referenced by at least one cell.
+    #[cfg(test)]
+    pub fn palette_len(&self) -> usize {
+        self.palette.iter().filter(|entry| entry.refs > 0).count()
+    }
+
+    pub fn get(&self, i: usize) -> T {
+        self.palette[self.index_at(i)].value
+    }
+
+    /// Stores `value` at `i` and returns the value it replaced.
+    pub fn set(&mut self, i: usize, value: T) -> T {
+        let old_index = self.index_at(i);
+        let old = self.palette[old_index].value;
+        if old == value {
+            return old;
+        }
+
+        let new_index = self.palette_index(value);
+        self.palette[old_index].refs -= 1;
+        self.palette[new_index].refs += 1;
+        self.write_index(i, new_index);
+        old
+    }
+
+    #[cfg(test)]
+    pub fn fill(&mut self, value: T) {
+        *self = Self::new(self.len, value);
+    }
+
+    /// Drops unreferenced palette entries and repacks the data with the smallest
+    /// index width that still fits.
+    pub fn compact(&mut self) {
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::with_capacity(self.palette.len());
+        for (old, entry) in self.palette.iter().enumerate() {
+            if entry.refs > 0 {
+                remap[old] = palette.len();
+                palette.push(entry.clone());
+            }
+        }
+        if palette.len() == self.palette.len() && width_for(palette.len()) == self.bits {
+            return;
+        }
+
+        let indices: Vec<usize> = (0..self.len).map(|i| remap[self.index_at(i)]).collect();
+        self.palette = palette;
+        self.repack(width_for(self.palette.len()), indices);
+    }
+
+    /// Finds `value` in the palette, reusing a free slot or growing the index
+    /// width when it is not there yet.
+    fn palette_index(&mut self, value: T) -> usize {
+        if let Some(index) = self.palette.iter().position(|entry| entry.value == value) {
+            return index;
+        }
+        let entry = PaletteEntry { value, refs: 0 };
+        if let Some(index) = self.palette.iter().position(|entry| entry.refs == 0) {
+            self.palette[index] = entry;
+            return index;
+        }
+
+        self.palette.push(entry);
+        let bits = width_for(self.palette.len());
+        if bits != self.bits {
+            let indices: Vec<usize> = (0..self.len).map(|i| self.index_at(i)).collect();
+            self.repack(bits, indices);
+        }
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, bits: u32, indices: Vec<usize>) {
+        self.bits = bits;
+        self.data = vec![0; words_for(self.len, bits)];
+        for (i, index) in indices.into_iter().enumerate() {
+            self.write_index(i, index);
+        }
+    }
+
+    fn index_at(&self, i: usize) -> usize {
+        assert!(
+            i < self.len,
+            "index {i} out of bounds for {} cells",
+            self.len
+        );
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.data[i / per_word] >> shift) & mask) as usize
+    }
+
+    fn write_index(&mut self, i: usize, index: usize) {
+        let per_word = (64 / self.bits) as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !(mask << shift)) | ((index as u64 & mask) << shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_with_single_entry_at_one_bit() {
+        let storage = PalettedStorage::new(4096, 0u16);
+        assert_eq!(storage.bits(), 1);
+        assert_eq!(storage.palette_len(), 1);
+        assert_eq!(storage.data.len(), 64);
+        assert!((0..4096).all(|i| storage.get(i) == 0));
+    }
+
+    #[test]
+    fn grows_through_every_width() {
+        let mut storage = PalettedStorage::new(4096, 0u16);
+
+        storage.set(1, 1);
+        assert_eq!(storage.bits(), 1);
+
+        storage.set(2, 2);
+        assert_eq!(storage.bits(), 4);
+
+        for value in 3..17 {
+            storage.set(value as usize, value);
+        }
+        assert_eq!(storage.bits(), 8);
+
+        for value in 17..257 {
+            storage.set(value as usize, value);
+        }
+        assert_eq!(storage.bits(), 16);
+        assert_eq!(storage.palette_len(), 257);
+
+        for i in 0..4096 {
+            let expected = if i < 257 { i as u16 } else { 0 };
+            assert_eq!(storage.get(i), expected);
+        }
+    }
+
+    #[test]
+    fn set_returns_previous_value() {
+        let mut storage = PalettedStorage::new(8, 7u16);
+        assert_eq!(storage.set(3, 9), 7);
+        assert_eq!(storage.set(3, 9), 9);
+        assert_eq!(storage.set(3, 7), 9);
+    }
+
+    #[test]
+    fn freed_entries_are_reused_without_growing() {
+        let mut storage = PalettedStorage::new(16, 0u16);
+        storage.set(0, 1);
+        storage.set(0, 0);
+        storage.set(0, 2);
+        assert_eq!(storage.bits(), 1);
+        assert_eq!(storage.palette.len(), 2);
+        assert_eq!(storage.get(0), 2);
+    }
+
+    #[test]
+    fn compact_shrinks_back_down() {
+        let mut storage = PalettedStorage::new(4096, 0u16);
+        for value in 1..300 {
+            storage.set(value as usize, value);
+        }
+        assert_eq!(storage.bits(), 16);
+
+        for i in 1..300 {
+            if i != 42 {
+                storage.set(i, 0);
+            }
+        }
+        assert_eq!(storage.bits(), 16);
+        storage.compact();
+        assert_eq!(storage.bits(), 1);
+        assert_eq!(storage.palette_len(), 2);
+        assert_eq!(storage.get(42), 42);
+        assert_eq!(storage.get(43), 0);
+
+        storage.set(42, 0);
+        storage.compact();
+        assert_eq!(storage.palette.len(), 1);
+        assert!((0..4096).all(|i| storage.get(i) == 0));
+    }
+
+    #[test]
+    fn compact_picks_smallest_fitting_width() {
+        let mut storage = PalettedStorage::new(64, 0u16);
+        for value in 1..20 {
+            storage.set(value as usize, value);
+        }
+        assert_eq!(storage.bits(), 8);
+        for i in 10..20 {
+            storage.set(i, 0);
+        }
+        storage.compact();
+        assert_eq!(storage.bits(), 4);
+        assert!((1..10).all(|i| storage.get(i) == i as u16));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::systems::aabb::Aabb;
+use macroquad::prelude::*;
+
+/// Blocks per second squared.
+pub const GRAVITY: f32 = 32.0;
+/// Fastest a body falls, in blocks per second.
+pub const TERMINAL_VELOCITY: f32 = 60.0;
+/// Upward speed of a jump, enough to clear a little over one block.
+pub const JUMP_SPEED: f32 = 9.0;
+/// Walking speed, in blocks per second.
+pub const WALK_SPEED: f32 = 4.3;
+
+/// Slack when deciding which blocks a box reaches into, so a box resting
+/// exactly on a block boundary, give or take rounding, is not counted as inside
+/// the block past it.
+const EPSILON: f32 = 1e-4;
+
+/// Blocks `range` covers along one axis, ignoring the slack at both ends.
+fn cells(min: f32, max: f32) -> std::ops::Range<i32> {
+    (min + EPSILON).floor() as i32..(max - EPSILON).ceil() as i32
+}
+
+/// How far `bounds` can move along `axis`, up to `distance`, before running into
+/// a solid block. Blocks the box already overlaps are ignored, so a box stuck
+/// in a block can still move out of it.
+fn sweep_axis(bounds: &Aabb, axis: usize, distance: f32, is_solid: &impl Fn(IVec3) -> bool) -> f32 {
+    if distance == 0.0 {
+        return 0.0;
+    }
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let layer_is_solid = |layer: i32| {
+        cells(bounds.min[a], bounds.max[a]).any(|i| {
+            cells(bounds.min[b], bounds.max[b]).any(|j| {
+                let mut pos = IVec3::ZERO;
+                pos[axis] = layer;
+                pos[a] = i;
+                pos[b] = j;
+                is_solid(pos)
+            })
+        })
+    };
+
+    if distance > 0.0 {
+        let front = bounds.max[axis];
+        let mut layer = (front - EPSILON).ceil() as i32;
+        while (layer as f32) < front + distance {
+            if layer_is_solid(layer) {
+                return (layer as f32 - front).clamp(0.0, distance);
+            }
+            layer += 1;
+        }
+    } else {
+        let front = bounds.min[axis];
+        let mut layer = (front + EPSILON).floor() as i32 - 1;
+        while (layer + 1) as f32 > front + distance {
+            if layer_is_solid(layer) {
+                return ((layer + 1) as f32 - front).clamp(distance, 0.0);
+            }
+            layer -= 1;
+        }
+    }
+    distance
+}
+
+/// Where a box ended up after `sweep`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    /// How far the box actually moved.
+    pub motion: Vec3,
+    /// The axes it was stopped on.
+    pub blocked: BVec3,
+}
+
+/// Moves `bounds` by `motion` through a world where `is_solid` holds for solid
+/// blocks, one axis at a time: up or down first, then along x, then along z.
+/// An axis that runs into a block stops there while the others carry on, so a
+/// box pushed diagonally into a wall slides along it. Every block in the way is
+/// checked however far the box moves, so nothing is skipped at high speed.
+pub fn sweep(bounds: Aabb, motion: Vec3, is_solid: impl Fn(IVec3) -> bool) -> Collision {
+    let mut bounds = bounds;
+    let mut moved = Vec3::ZERO;
+    let mut blocked = BVec3::FALSE;
+    for axis in [1, 0, 2] {
+        let distance = sweep_axis(&bounds, axis, motion[axis], &is_solid);
+        moved[axis] = distance;
+        blocked.set(axis, distance != motion[axis]);
+        bounds.min[axis] += distance;
+        bounds.max[axis] += distance;
+    }
+    Collision {
+        motion: moved,
+        blocked,
+    }
+}
+
+/// Velocity and ground contact of something that walks, falls and jumps.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+    pub velocity: Vec3,
+    pub on_ground: bool,
+}
+
+impl Body {
+    /// Advances the body with its box at `bounds` by `dt` seconds and returns how
+    /// far it moved. `wish` is the horizontal direction to walk in, at most one
+    /// long; `jump` only takes off from the ground.
+    pub fn step(
+        &mut self,
+        bounds: Aabb,
+        wish: Vec3,
+        jump: bool,
+        dt: f32,
+        is_solid: impl Fn(IVec3) -> bool,
+    ) -> Vec3 {
+        self.velocity.x = wish.x * WALK_SPEED;
+        self.velocity.z = wish.z * WALK_SPEED;
+        if jump && self.on_ground {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-TERMINAL_VELOCITY);
+
+        let collision = sweep(bounds, self.velocity * dt, is_solid);
+        self.on_ground = collision.blocked.y && self.velocity.y < 0.0;
+        for axis in 0..3 {
+            if collision.blocked.test(axis) {
+                self.velocity[axis] = 0.0;
+            }
+        }
+        collision.motion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A player sized box with its feet at `feet`.
+    fn player(feet: Vec3) -> Aabb {
+        Aabb::new(feet - vec3(0.3, 0.0, 0.3), feet + vec3(0.3, 1.8, 0.3))
+    }
+
+    fn feet(bounds: &Aabb) -> Vec3 {
+        vec3(
+            (bounds.min.x + bounds.max.x) / 2.0,
+            bounds.min.y,
+            (bounds.min.z + bounds.max.z) / 2.0,
+        )
+    }
+
+    fn moved(bounds: Aabb, motion: Vec3) -> Aabb {
+        Aabb::new(bounds.min + motion, bounds.max + motion)
+    }
+
+    /// Solid ground: every block with y below 1.
+    fn ground(pos: IVec3) -> bool {
+        pos.y < 1
+    }
+
+    /// Steps a body `steps` times and returns its final box.
+    fn run(
+        body: &mut Body,
+        mut bounds: Aabb,
+        steps: usize,
+        wish: Vec3,
+        jump: bool,
+        is_solid: impl Fn(IVec3) -> bool,
+    ) -> Aabb {
+        for _ in 0..steps {
+            let motion = body.step(bounds, wish, jump, DT, &is_solid);
+            bounds = moved(bounds, motion);
+        }
+        bounds
+    }
+
+    #[test]
+    fn falls_onto_the_ground() {
+        let mut body = Body::default();
+        let bounds = run(
+            &mut body,
+            player(vec3(0.5, 6.0, 0.5)),
+            120,
+            Vec3::ZERO,
+            false,
+            ground,
+        );
+        assert!((feet(&bounds).y - 1.0).abs() < 1e-4, "{bounds:?}");
+        assert!(body.on_ground);
+        assert_eq!(body.velocity, Vec3::ZERO);
+        // And stays there.
+        let still = run(&mut body, bounds, 60, Vec3::ZERO, false, ground);
+        assert_eq!(still, bounds);
+        assert!(body.on_ground);
+    }
+
+    #[test]
+    fn does_not_fall_through_thin_floors_at_any_speed() {
+        let floor = |pos: IVec3| pos.y == -40;
+        let collision = sweep(player(vec3(0.5, 100.0, 0.5)), vec3(0.0, -500.0, 0.0), floor);
+        assert!(collision.blocked.y);
+        assert!((collision.motion.y + 139.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        // A wall filling x 3 and up, on top of the ground.
+        let world = |pos: IVec3| ground(pos) || pos.x >= 3;
+        let mut body = Body::default();
+        let start = player(vec3(1.5, 1.0, 0.5));
+        let wish = vec3(1.0, 0.0, 1.0).normalize();
+        let bounds = run(&mut body, start, 60, wish, false, world);
+        let end = feet(&bounds);
+        assert!((bounds.max.x - 3.0).abs() < 1e-4, "{bounds:?}");
+        // Sliding on at the full speed along z, and still on the ground.
+        assert!((end.z - 0.5 - WALK_SPEED * wish.z).abs() < 1e-3, "{end}");
+        assert!(body.on_ground);
+
+        // Walking straight along the wall, touching it, never catches on it.
+        let collision = sweep(bounds, vec3(0.0, 0.0, 7.0), world);
+        assert_eq!(collision.motion, vec3(0.0, 0.0, 7.0));
+        assert!(!collision.blocked.any());
+    }
+
+    #[test]
+    fn stops_in_corners() {
+        let world = |pos: IVec3| ground(pos) || pos.x >= 3 || pos.z <= -2;
+        let collision = sweep(player(vec3(1.5, 1.0, 0.5)), vec3(4.0, 0.0, -4.0), world);
+        assert_eq!(collision.blocked, BVec3::new(true, false, true));
+        assert!((collision.motion.x - 1.2).abs() < 1e-4);
+        assert!((collision.motion.z + 1.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn jumps_onto_a_block() {
+        // A one block step at x 2.
+        let world = |pos: IVec3| ground(pos) || (pos.x >= 2 && pos.y == 1);
+        let mut body = Body {
+            on_ground: true,
+            ..Body::default()
+        };
+        let start = player(vec3(1.5, 1.0, 0.5));
+        // Walking into the step does not climb it.
+        let bounds = run(&mut body, start, 30, Vec3::X, false, world);
+        assert!((feet(&bounds).y - 1.0).abs() < 1e-4);
+        assert!((bounds.max.x - 2.0).abs() < 1e-4);
+        // Jumping does.
+        let bounds = run(&mut body, bounds, 1, Vec3::X, true, world);
+        let bounds = run(&mut body, bounds, 60, Vec3::X, false, world);
+        assert!((feet(&bounds).y - 2.0).abs() < 1e-4, "{bounds:?}");
+        assert!(feet(&bounds).x > 3.0);
+        assert!(body.on_ground);
+    }
+
+    #[test]
+    fn jumps_are_cut_short_by_ceilings() {
+        // Two blocks of headroom over the ground.
+        let world = |pos: IVec3| ground(pos) || pos.y >= 3;
+        let mut body = Body {
+            on_ground: true,
+            ..Body::default()
+        };
+        let start = player(vec3(0.5, 1.0, 0.5));
+        let motion = body.step(start, Vec3::ZERO, true, DT, world);
+        let mut bounds = moved(start, motion);
+        let mut highest = bounds.max.y;
+        for _ in 0..60 {
+            let motion = body.step(bounds, Vec3::ZERO, false, DT, world);
+            bounds = moved(bounds, motion);
+            highest = highest.max(bounds.max.y);
+        }
+        assert!(highest <= 3.0 + 1e-4, "{highest}");
+        assert!(highest > 2.9);
+        assert!(body.on_ground);
+    }
+
+    #[test]
+    fn walking_off_a_ledge_falls() {
+        let world = |pos: IVec3| pos.y < 1 && pos.x < 2 || pos.y < -5;
+        let mut body = Body {
+            on_ground: true,
+            ..Body::default()
+        };
+        let bounds = run(
+            &mut body,
+            player(vec3(1.0, 1.0, 0.5)),
+            20,
+            Vec3::X,
+            false,
+            world,
+        );
+        assert!(!body.on_ground);
+        assert!(body.velocity.y < 0.0);
+        assert!(feet(&bounds).y < 1.0);
+        let bounds = run(&mut body, bounds, 120, Vec3::ZERO, false, world);
+        assert!((feet(&bounds).y + 5.0).abs() < 1e-4);
+    }
+}
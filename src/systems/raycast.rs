@@ -0,0 +1,225 @@
+use macroquad::prelude::*;
+
+/// The first solid block a ray runs into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// World position of the block.
+    pub block: IVec3,
+    /// Normal of the face the ray entered through, pointing back at the ray.
+    /// Zero if the ray started inside the block.
+    pub normal: IVec3,
+    /// Distance along the ray to where it entered the block.
+    pub distance: f32,
+}
+
+/// Walks the blocks along the ray from `origin` in `direction` one boundary at a
+/// time (Amanatides and Woo's DDA) and returns the first one `is_solid` holds
+/// for, up to `max_distance` away.
+///
+/// A ray through the exact edge or corner of a block passes between the blocks
+/// sharing it straight into the diagonal one, so grazing a block never hits it.
+/// The reported normal is then the axis the ray moves along fastest.
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    is_solid: impl Fn(IVec3) -> bool,
+) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let mut block = origin.floor().as_ivec3();
+    if is_solid(block) {
+        return Some(RayHit {
+            block,
+            normal: IVec3::ZERO,
+            distance: 0.0,
+        });
+    }
+
+    let step = IVec3::from_array(direction.to_array().map(|d| {
+        if d > 0.0 {
+            1
+        } else if d < 0.0 {
+            -1
+        } else {
+            0
+        }
+    }));
+    // Distance along the ray between two boundaries on each axis.
+    let delta = direction.abs().recip();
+    // Distance along the ray to the next boundary on each axis.
+    let mut next = Vec3::from_array(std::array::from_fn(|axis| match step[axis] {
+        1 => (block[axis] as f32 + 1.0 - origin[axis]) * delta[axis],
+        -1 => (origin[axis] - block[axis] as f32) * delta[axis],
+        _ => f32::INFINITY,
+    }));
+
+    loop {
+        let distance = next.min_element();
+        if distance > max_distance {
+            return None;
+        }
+        let mut normal_axis = None;
+        for axis in 0..3 {
+            if next[axis] == distance {
+                block[axis] += step[axis];
+                next[axis] += delta[axis];
+                if normal_axis
+                    .is_none_or(|other: usize| direction[axis].abs() > direction[other].abs())
+                {
+                    normal_axis = Some(axis);
+                }
+            }
+        }
+        if is_solid(block) {
+            let mut normal = IVec3::ZERO;
+            let axis = normal_axis.unwrap();
+            normal[axis] = -step[axis];
+            return Some(RayHit {
+                block,
+                normal,
+                distance,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQRT_2: f32 = std::f32::consts::SQRT_2;
+
+    fn solid_at(blocks: &[IVec3]) -> impl Fn(IVec3) -> bool + '_ {
+        |pos| blocks.contains(&pos)
+    }
+
+    fn assert_hit(hit: Option<RayHit>, block: IVec3, normal: IVec3, distance: f32) {
+        let hit = hit.expect("expected a hit");
+        assert_eq!((hit.block, hit.normal), (block, normal));
+        assert!(
+            (hit.distance - distance).abs() < 1e-5,
+            "distance {} instead of {distance}",
+            hit.distance
+        );
+    }
+
+    #[test]
+    fn rays_along_every_axis() {
+        let origin = vec3(0.5, 0.5, 0.5);
+        for axis in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            let blocks = [axis * 5, axis * 7];
+            let hit = raycast(origin, axis.as_vec3(), 10.0, solid_at(&blocks));
+            assert_hit(hit, axis * 5, -axis, 4.5);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_reach() {
+        let blocks = [ivec3(0, 0, 8)];
+        let origin = vec3(0.5, 0.5, 0.5);
+        assert!(raycast(origin, Vec3::Z, 7.0, solid_at(&blocks)).is_none());
+        assert_hit(
+            raycast(origin, Vec3::Z, 7.5, solid_at(&blocks)),
+            ivec3(0, 0, 8),
+            IVec3::NEG_Z,
+            7.5,
+        );
+        assert!(raycast(origin, Vec3::Z, 100.0, solid_at(&[])).is_none());
+        assert!(raycast(origin, Vec3::ZERO, 100.0, solid_at(&blocks)).is_none());
+    }
+
+    #[test]
+    fn looking_down_at_the_floor() {
+        let floor: Vec<IVec3> = (-8..8)
+            .flat_map(|x| (-8..8).map(move |z| ivec3(x, 0, z)))
+            .collect();
+        let hit = raycast(
+            vec3(0.5, 5.2, 0.5),
+            vec3(1.0, -1.0, 0.0),
+            10.0,
+            solid_at(&floor),
+        );
+        assert_hit(hit, ivec3(4, 0, 0), IVec3::Y, 4.2 * SQRT_2);
+        // Negative coordinates, and a direction the length does not matter for.
+        let hit = raycast(
+            vec3(-3.25, 2.5, -6.75),
+            vec3(0.0, -3.0, -2.0),
+            10.0,
+            solid_at(&floor),
+        );
+        let distance = vec2(1.5, 1.0).length();
+        assert_hit(hit, ivec3(-4, 0, -8), IVec3::Y, distance);
+    }
+
+    #[test]
+    fn starting_inside_a_block() {
+        let hit = raycast(
+            vec3(2.5, 1.0, -0.5),
+            Vec3::X,
+            5.0,
+            solid_at(&[ivec3(2, 1, -1)]),
+        );
+        assert_hit(hit, ivec3(2, 1, -1), IVec3::ZERO, 0.0);
+    }
+
+    #[test]
+    fn edges_lead_into_the_diagonal_block() {
+        let origin = vec3(0.5, 0.5, 0.5);
+        let direction = vec3(1.0, 1.0, 0.0);
+        // The blocks on either side of the edge are only grazed.
+        let sides = [ivec3(1, 0, 0), ivec3(0, 1, 0), ivec3(2, 2, 0)];
+        assert_hit(
+            raycast(origin, direction, 10.0, solid_at(&sides)),
+            ivec3(2, 2, 0),
+            IVec3::NEG_X,
+            1.5 * SQRT_2,
+        );
+        let diagonal = [ivec3(1, 1, 0)];
+        assert_hit(
+            raycast(origin, direction, 10.0, solid_at(&diagonal)),
+            ivec3(1, 1, 0),
+            IVec3::NEG_X,
+            0.5 * SQRT_2,
+        );
+        // Steeper in y, so the normal faces down.
+        let hit = raycast(
+            vec3(0.5, 0.0, 0.5),
+            vec3(1.0, 2.0, 0.0),
+            10.0,
+            solid_at(&diagonal),
+        );
+        assert_hit(hit, ivec3(1, 1, 0), IVec3::NEG_Y, vec2(0.5, 1.0).length());
+    }
+
+    #[test]
+    fn corners_lead_into_the_diagonal_block() {
+        let origin = vec3(0.5, 0.5, 0.5);
+        let direction = vec3(-1.0, -1.0, -1.0);
+        let neighbours = [
+            ivec3(-1, 0, 0),
+            ivec3(0, -1, 0),
+            ivec3(0, 0, -1),
+            ivec3(-1, -1, 0),
+            ivec3(-1, 0, -1),
+            ivec3(0, -1, -1),
+        ];
+        assert!(raycast(origin, direction, 10.0, solid_at(&neighbours)).is_none());
+        let corner = [ivec3(-1, -1, -1)];
+        assert_hit(
+            raycast(origin, direction, 10.0, solid_at(&corner)),
+            ivec3(-1, -1, -1),
+            IVec3::X,
+            0.5 * 3f32.sqrt(),
+        );
+    }
+}
@@ -0,0 +1,496 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::systems::{
+    atlas::{AtlasLayout, AtlasTile},
+    blocks::{BlockSides, BlockType},
+};
+
+/// Highest light level a block may emit.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Everything the engine knows about one kind of block.
+#[derive(Clone, Debug)]
+pub struct BlockDef {
+    pub id: BlockType,
+    pub name: String,
+    /// Atlas tile of every side, in `BlockSides::ALL` order, or `None` for blocks
+    /// that are never drawn.
+    pub faces: Option<[AtlasTile; 6]>,
+    pub solid: bool,
+    pub transparent: bool,
+    pub light_emission: u8,
+    pub hardness: f32,
+}
+
+impl BlockDef {
+    pub fn face(&self, side: BlockSides) -> Option<AtlasTile> {
+        self.faces.map(|faces| faces[side as usize])
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(PathBuf, io::Error),
+    Parse(String),
+    DuplicateId {
+        id: u16,
+        first: String,
+        second: String,
+    },
+    DuplicateName(String),
+    InvalidAir,
+    LightTooHigh {
+        block: String,
+        level: u8,
+    },
+    MissingTexture {
+        block: String,
+        side: BlockSides,
+    },
+    UnknownBlock(String),
+    UnknownTexture {
+        block: String,
+        side: BlockSides,
+        name: String,
+    },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            RegistryError::Parse(err) => write!(f, "invalid block definitions: {}", err),
+            RegistryError::DuplicateId { id, first, second } => write!(
+                f,
+                "blocks \"{}\" and \"{}\" both use id {}",
+                first, second, id
+            ),
+            RegistryError::DuplicateName(name) => {
+                write!(f, "block \"{}\" is defined more than once", name)
+            }
+            RegistryError::InvalidAir => write!(
+                f,
+                "id 0 must be a transparent block without textures, since empty chunks are filled with it"
+            ),
+            RegistryError::LightTooHigh { block, level } => write!(
+                f,
+                "block \"{}\" emits light level {}, the maximum is {}",
+                block, level, MAX_LIGHT
+            ),
+            RegistryError::MissingTexture { block, side } => {
+                write!(f, "block \"{}\" has no texture for its {:?} side", block, side)
+            }
+            RegistryError::UnknownBlock(name) => write!(f, "block \"{}\" is not defined", name),
+            RegistryError::UnknownTexture { block, side, name } => write!(
+                f,
+                "texture \"{}\" for the {:?} side of block \"{}\" is not in the atlas",
+                name, side, block
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    block: Vec<RawBlock>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBlock {
+    id: u16,
+    name: String,
+    textures: Option<RawTextures>,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+/// Texture names as written in the file. Single faces override `side`, which
+/// overrides `all`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTextures {
+    all: Option<String>,
+    side: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    front: Option<String>,
+    back: Option<String>,
+    left: Option<String>,
+    right: Option<String>,
+}
+
+impl RawTextures {
+    fn get(&self, side: BlockSides) -> Option<&str> {
+        let (face, vertical) = match side {
+            BlockSides::Top => (&self.top, false),
+            BlockSides::Bottom => (&self.bottom, false),
+            BlockSides::Front => (&self.front, true),
+            BlockSides::Back => (&self.back, true),
+            BlockSides::Left => (&self.left, true),
+            BlockSides::Right => (&self.right, true),
+        };
+        face.as_ref()
+            .or(self.side.as_ref().filter(|_| vertical))
+            .or(self.all.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// All block definitions, indexed by id, with their textures resolved against
+/// the atlas.
+#[derive(Clone, Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockDef>>,
+    names: HashMap<String, BlockType>,
+}
+
+impl BlockRegistry {
+    pub fn load(path: impl AsRef<Path>, atlas: &AtlasLayout) -> Result<Self, RegistryError> {
+        let path = path.as_ref();
+        let source =
+            fs::read_to_string(path).map_err(|err| RegistryError::Io(path.to_path_buf(), err))?;
+        Self::from_toml(&source, atlas)
+    }
+
+    pub fn from_toml(source: &str, atlas: &AtlasLayout) -> Result<Self, RegistryError> {
+        let definitions: Definitions =
+            toml::from_str(source).map_err(|err| RegistryError::Parse(err.to_string()))?;
+
+        let mut registry = Self {
+            blocks: Vec::new(),
+            names: HashMap::new(),
+        };
+        for raw in definitions.block {
+            let def = Self::resolve(raw, atlas)?;
+            registry.insert(def)?;
+        }
+
+        match registry.blocks.first() {
+            Some(Some(air)) if air.transparent && air.faces.is_none() => Ok(registry),
+            _ => Err(RegistryError::InvalidAir),
+        }
+    }
+
+    fn resolve(raw: RawBlock, atlas: &AtlasLayout) -> Result<BlockDef, RegistryError> {
+        if raw.light_emission > MAX_LIGHT {
+            return Err(RegistryError::LightTooHigh {
+                block: raw.name,
+                level: raw.light_emission,
+            });
+        }
+
+        let faces = match &raw.textures {
+            None => None,
+            Some(textures) => {
+                let mut faces = Vec::with_capacity(6);
+                for side in BlockSides::ALL {
+                    let name = textures
+                        .get(side)
+                        .ok_or_else(|| RegistryError::MissingTexture {
+                            block: raw.name.clone(),
+                            side,
+                        })?;
+                    let tile = atlas
+                        .tile(name)
+                        .ok_or_else(|| RegistryError::UnknownTexture {
+                            block: raw.name.clone(),
+                            side,
+                            name: name.to_string(),
+                        })?;
+                    faces.push(tile);
+                }
+                Some(faces.try_into().unwrap())
+            }
+        };
+
+        Ok(BlockDef {
+            id: BlockType(raw.id),
+            name: raw.name,
+            faces,
+            solid: raw.solid,
+            transparent: raw.transparent,
+            light_emission: raw.light_emission,
+            hardness: raw.hardness,
+        })
+    }
+
+    fn insert(&mut self, def: BlockDef) -> Result<(), RegistryError> {
+        if self.names.contains_key(&def.name) {
+            return Err(RegistryError::DuplicateName(def.name));
+        }
+        let index = def.id.0 as usize;
+        if index >= self.blocks.len() {
+            self.blocks.resize(index + 1, None);
+        }
+        if let Some(first) = &self.blocks[index] {
+            return Err(RegistryError::DuplicateId {
+                id: def.id.0,
+                first: first.name.clone(),
+                second: def.name,
+            });
+        }
+        self.names.insert(def.name.clone(), def.id);
+        self.blocks[index] = Some(def);
+        Ok(())
+    }
+
+    /// Id of the block called `name`.
+    pub fn id(&self, name: &str) -> Option<BlockType> {
+        self.names.get(name).copied()
+    }
+
+    /// Like [`BlockRegistry::id`], for blocks the engine cannot do without.
+    pub fn require(&self, name: &str) -> Result<BlockType, RegistryError> {
+        self.id(name)
+            .ok_or_else(|| RegistryError::UnknownBlock(name.to_string()))
+    }
+
+    /// Panics for ids that are not defined, which never end up in the world.
+    pub fn get(&self, block_type: BlockType) -> &BlockDef {
+        self.blocks
+            .get(block_type.0 as usize)
+            .and_then(Option::as_ref)
+            .unwrap_or_else(|| panic!("block id {} is not registered", block_type.0))
+    }
+
+    /// Every registered block, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDef> {
+        self.blocks.iter().flatten()
+    }
+
+    pub fn is_drawn(&self, block_type: BlockType) -> bool {
+        self.get(block_type).faces.is_some()
+    }
+
+    pub fn is_transparent(&self, block_type: BlockType) -> bool {
+        self.get(block_type).transparent
+    }
+
+    pub fn is_solid(&self, block_type: BlockType) -> bool {
+        self.get(block_type).solid
+    }
+
+    /// Whether `block_type` can be broken at all. A negative hardness marks
+    /// blocks that cannot.
+    pub fn is_breakable(&self, block_type: BlockType) -> bool {
+        self.get(block_type).hardness >= 0.0
+    }
+
+    pub fn light_emission(&self, block_type: BlockType) -> u8 {
+        self.get(block_type).light_emission
+    }
+
+    /// Tile for `side` of `block_type`. Blocks that are never drawn have no tiles.
+    pub fn face(&self, block_type: BlockType, side: BlockSides) -> AtlasTile {
+        self.get(block_type)
+            .face(side)
+            .unwrap_or_else(|| panic!("{} is never drawn", self.get(block_type).name))
+    }
+}
+
+/// Definitions shipped in `assets/blocks.toml`.
+pub const DEFAULT_BLOCKS: &str = include_str!("../../assets/blocks.toml");
+
+/// The shipped definitions on the shipped atlas, shared by the tests of other
+/// modules.
+#[cfg(test)]
+pub fn test_registry() -> BlockRegistry {
+    use crate::systems::atlas::DEFAULT_ATLAS_TILES;
+
+    let atlas = AtlasLayout::new(1, DEFAULT_ATLAS_TILES.len() as u32, &DEFAULT_ATLAS_TILES);
+    BlockRegistry::from_toml(DEFAULT_BLOCKS, &atlas).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::atlas::{TextureAtlas, DEFAULT_ATLAS_TILES};
+
+    fn atlas() -> AtlasLayout {
+        AtlasLayout::new(1, DEFAULT_ATLAS_TILES.len() as u32, &DEFAULT_ATLAS_TILES)
+    }
+
+    fn load(source: &str) -> Result<BlockRegistry, RegistryError> {
+        BlockRegistry::from_toml(source, &atlas())
+    }
+
+    const AIR: &str = "[[block]]\nid = 0\nname = \"air\"\nsolid = false\ntransparent = true\n";
+
+    #[test]
+    fn loads_the_shipped_definitions() {
+        let registry = test_registry();
+        let stone = registry.require("stone").unwrap();
+        let def = registry.get(stone);
+        assert_eq!(def.name, "stone");
+        assert!(def.solid);
+        assert!(!def.transparent);
+        assert_eq!(def.hardness, 1.5);
+        assert_eq!(def.light_emission, 0);
+
+        let air = registry.get(BlockType::AIR);
+        assert_eq!(air.name, "air");
+        assert!(!air.solid);
+        assert!(!registry.is_solid(BlockType::AIR));
+        assert!(registry.is_solid(stone));
+        assert!(registry.is_breakable(stone));
+        assert_eq!(registry.light_emission(stone), 0);
+        assert!(!registry.is_drawn(BlockType::AIR));
+        assert!(registry.is_drawn(stone));
+        assert_eq!(
+            registry.require("lava").unwrap_err().to_string(),
+            "block \"lava\" is not defined"
+        );
+    }
+
+    #[test]
+    fn grass_has_per_side_tiles() {
+        let registry = test_registry();
+        let layout = atlas();
+        let grass = registry.id("grass").unwrap();
+        let face = |side| registry.face(grass, side);
+        assert_eq!(face(BlockSides::Top), layout.tile("grass").unwrap());
+        assert_eq!(face(BlockSides::Bottom), layout.tile("dirt").unwrap());
+        for side in [
+            BlockSides::Front,
+            BlockSides::Back,
+            BlockSides::Left,
+            BlockSides::Right,
+        ] {
+            assert_eq!(face(side), layout.tile("grass_side").unwrap());
+        }
+    }
+
+    #[test]
+    fn single_faces_override_side_and_all() {
+        let registry = load(&format!(
+            "{AIR}[[block]]\nid = 7\nname = \"odd\"\nlight_emission = 12\n\
+             textures = {{ all = \"stone\", side = \"dirt\", left = \"grass\" }}\n"
+        ))
+        .unwrap();
+        let layout = atlas();
+        let odd = registry.id("odd").unwrap();
+        assert_eq!(odd, BlockType(7));
+        assert_eq!(registry.get(odd).light_emission, 12);
+        assert_eq!(registry.light_emission(odd), 12);
+        assert_eq!(
+            registry.face(odd, BlockSides::Top),
+            layout.tile("stone").unwrap()
+        );
+        assert_eq!(
+            registry.face(odd, BlockSides::Front),
+            layout.tile("dirt").unwrap()
+        );
+        assert_eq!(
+            registry.face(odd, BlockSides::Left),
+            layout.tile("grass").unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_texture_names_the_block_and_side() {
+        let err = load(&format!(
+            "{AIR}[[block]]\nid = 1\nname = \"glass\"\ntextures = {{ all = \"glass\" }}\n"
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            RegistryError::UnknownTexture { ref block, side: BlockSides::Top, ref name }
+                if block == "glass" && name == "glass"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "texture \"glass\" for the Top side of block \"glass\" is not in the atlas"
+        );
+    }
+
+    #[test]
+    fn incomplete_textures_are_an_error() {
+        let err = load(&format!(
+            "{AIR}[[block]]\nid = 1\nname = \"log\"\ntextures = {{ top = \"dirt\", side = \"stone\" }}\n"
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            RegistryError::MissingTexture {
+                side: BlockSides::Bottom,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let err = load(&format!(
+            "{AIR}[[block]]\nid = 0\nname = \"void\"\nsolid = false\ntransparent = true\n"
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            RegistryError::DuplicateId { id: 0, ref first, ref second }
+                if first == "air" && second == "void"
+        ));
+
+        let err = load(&format!("{AIR}[[block]]\nid = 1\nname = \"air\"\n")).unwrap_err();
+        assert!(matches!(err, RegistryError::DuplicateName(ref name) if name == "air"));
+    }
+
+    #[test]
+    fn id_zero_must_be_air() {
+        let stone_only = "[[block]]\nid = 1\nname = \"stone\"\ntextures = { all = \"stone\" }\n";
+        assert!(matches!(load(stone_only), Err(RegistryError::InvalidAir)));
+
+        let drawn_air = "[[block]]\nid = 0\nname = \"air\"\ntextures = { all = \"stone\" }\n";
+        assert!(matches!(load(drawn_air), Err(RegistryError::InvalidAir)));
+    }
+
+    #[test]
+    fn malformed_files_are_parse_errors() {
+        assert!(matches!(
+            load("[[block]]\nid = \"one\"\nname = \"air\"\n"),
+            Err(RegistryError::Parse(_))
+        ));
+        assert!(matches!(
+            load(&format!(
+                "{AIR}[[block]]\nid = 1\nname = \"x\"\nsolidity = true\n"
+            )),
+            Err(RegistryError::Parse(_))
+        ));
+        assert!(matches!(
+            load(&format!(
+                "{AIR}[[block]]\nid = 1\nname = \"lamp\"\nlight_emission = 16\n"
+            )),
+            Err(RegistryError::LightTooHigh { level: 16, .. })
+        ));
+    }
+
+    #[test]
+    fn shipped_definitions_resolve_on_the_stitched_atlas() {
+        let atlas = TextureAtlas::from_dir("assets/textures/blocks").unwrap();
+        let registry = BlockRegistry::from_toml(DEFAULT_BLOCKS, &atlas.layout).unwrap();
+        let dirt = registry.id("dirt").unwrap();
+        assert_eq!(
+            registry.face(dirt, BlockSides::Back),
+            atlas.layout.tile("dirt").unwrap()
+        );
+    }
+}
@@ -1,142 +1,256 @@
-use crate::systems::blocks::BlockMesh;
+use crate::systems::{
+    atlas::AtlasTile,
+    blocks::{BlockMesh, BlockSides},
+};
 use macroquad::prelude::*;
 
-fn vert(pos: Vec3, uv: Vec2) -> Vertex {
+/// Sub-meshes are split before their vertex count would leave the `u16` index range.
+pub const MAX_MESH_VERTICES: usize = u16::MAX as usize;
+/// Worst case index count of a sub-mesh: six indices for every four vertices.
+pub const MAX_MESH_INDICES: usize = MAX_MESH_VERTICES / 4 * 6;
+
+/// Brightness baked into the vertex colour of each side, so the faces of a cube
+/// stay distinguishable without any lighting: top brightest, bottom darkest.
+pub fn shade(side: BlockSides) -> f32 {
+    match side {
+        BlockSides::Top => 1.0,
+        BlockSides::Front | BlockSides::Back => 0.8,
+        BlockSides::Left | BlockSides::Right => 0.6,
+        BlockSides::Bottom => 0.5,
+    }
+}
+
+/// Vertex brightness for an ambient occlusion level, from fully enclosed (0) to
+/// unoccluded (3).
+pub const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+/// Ambient occlusion of a face nothing is touching.
+pub const NO_AO: [u8; 4] = [3; 4];
+
+/// Corners of `side` on the unit cube, in the order the quad vertices are emitted.
+pub fn face_corners(side: BlockSides) -> [Vec3; 4] {
+    match side {
+        BlockSides::Top => [
+            vec3(0.0, 1.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(0.0, 1.0, 1.0),
+        ],
+        BlockSides::Bottom => [
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 1.0),
+            vec3(0.0, 0.0, 1.0),
+        ],
+        BlockSides::Front => [
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+        ],
+        BlockSides::Back => [
+            vec3(0.0, 0.0, 1.0),
+            vec3(1.0, 0.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(0.0, 1.0, 1.0),
+        ],
+        BlockSides::Left => [
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 0.0),
+        ],
+        BlockSides::Right => [
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, 1.0, 1.0),
+        ],
+    }
+}
+
+/// The face normal goes in `normal.xyz` and the atlas tile index in `normal.w`.
+fn vert(pos: Vec3, uv: Vec2, side: BlockSides, tile: AtlasTile, ao: u8) -> Vertex {
+    let light = shade(side) * AO_BRIGHTNESS[ao as usize];
     Vertex {
         position: pos,
         uv,
-        color: WHITE.into(),
-        normal: vec4(0.0, 1.0, 0.0, 0.0),
+        color: Color::new(light, light, light, 1.0).into(),
+        normal: side.normal().extend(tile.index as f32),
     }
 }
-pub fn mesh_top(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 0.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 0.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 0.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 1.0), vec2(1.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(0.0, 0.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
+
+/// Appends the face `side` of the box at `pos` with extents `size`, as four
+/// vertices and two triangles. `uv` maps a corner scaled by `size` to its
+/// coordinate inside `tile`, so faces larger than one block repeat the tile once
+/// per block.
+///
+/// `ao` holds the occlusion level of every corner. The quad is split along the
+/// diagonal with the brighter ends, so a single dark corner shades one triangle
+/// instead of bleeding across the whole face.
+fn quad(
+    mesh: &mut BlockMesh,
+    side: BlockSides,
+    pos: Vec3,
+    size: Vec3,
+    tile: AtlasTile,
+    ao: [u8; 4],
+    uv: impl Fn(Vec3) -> Vec2,
+) {
+    let base = mesh.idx_counter as u16;
+    for (corner, ao) in face_corners(side).into_iter().zip(ao) {
+        let offset = corner * size;
+        mesh.vertices
+            .push(vert(pos + offset, tile.uv(uv(offset)), side, tile, ao));
     }
+    let indices = if ao[0] + ao[2] >= ao[1] + ao[3] {
+        [0, 1, 2, 0, 3, 2]
+    } else {
+        [1, 2, 3, 1, 0, 3]
+    };
+    mesh.indices.extend(indices.map(|i| base + i));
+    mesh.idx_counter += 4;
+}
 
-    mesh.texture = Some(texture);
+pub fn mesh_top(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Top, pos, size, tile, ao, |p| {
+        vec2(size.x - p.x, size.z - p.z)
+    });
 }
 
-pub fn mesh_bottom(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 0.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 0.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 1.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 0.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 1.0), vec2(1.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 1.0), vec2(0.0, 0.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
-    }
+pub fn mesh_bottom(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Bottom, pos, size, tile, ao, |p| {
+        vec2(size.x - p.x, size.z - p.z)
+    });
+}
 
-    mesh.texture = Some(texture);
+pub fn mesh_front(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Front, pos, size, tile, ao, |p| {
+        vec2(p.x, p.y)
+    });
 }
 
-pub fn mesh_front(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 0.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 0.0), vec2(1.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 0.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 0.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 0.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 0.0), vec2(1.0, 1.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
-    }
+pub fn mesh_back(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Back, pos, size, tile, ao, |p| {
+        vec2(p.x, p.y)
+    });
+}
 
-    mesh.texture = Some(texture);
+pub fn mesh_left(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Left, pos, size, tile, ao, |p| {
+        vec2(p.y, p.z)
+    });
 }
 
-pub fn mesh_back(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 1.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 1.0), vec2(1.0, 0.0)));
-    mesh.vertices   
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 1.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 1.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(1.0, 1.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
+pub fn mesh_right(mesh: &mut BlockMesh, pos: Vec3, size: Vec3, tile: AtlasTile, ao: [u8; 4]) {
+    quad(mesh, BlockSides::Right, pos, size, tile, ao, |p| {
+        vec2(p.y, p.z)
+    });
+}
+
+pub fn mesh_side(
+    mesh: &mut BlockMesh,
+    side: BlockSides,
+    pos: Vec3,
+    size: Vec3,
+    tile: AtlasTile,
+    ao: [u8; 4],
+) {
+    match side {
+        BlockSides::Top => mesh_top(mesh, pos, size, tile, ao),
+        BlockSides::Bottom => mesh_bottom(mesh, pos, size, tile, ao),
+        BlockSides::Front => mesh_front(mesh, pos, size, tile, ao),
+        BlockSides::Back => mesh_back(mesh, pos, size, tile, ao),
+        BlockSides::Left => mesh_left(mesh, pos, size, tile, ao),
+        BlockSides::Right => mesh_right(mesh, pos, size, tile, ao),
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    mesh.texture = Some(texture);
-} 
-
-pub fn mesh_left(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 0.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 1.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 0.0, 0.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 0.0), vec2(1.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(1.0, 1.0, 1.0), vec2(1.0, 1.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
+    fn tile() -> AtlasTile {
+        AtlasTile {
+            index: 3,
+            rect: Rect::new(0.0, 0.75, 1.0, 0.25),
+        }
     }
 
-    mesh.texture = Some(texture);
-}
+    #[test]
+    fn every_face_carries_its_outward_normal() {
+        let pos = vec3(2.0, -3.0, 5.0);
+        let center = pos + Vec3::splat(0.5);
+        for side in BlockSides::ALL {
+            let mut mesh = BlockMesh::new();
+            mesh_side(&mut mesh, side, pos, Vec3::ONE, tile(), NO_AO);
+            assert_eq!(mesh.vertices.len(), 4);
+
+            let face_center = mesh
+                .vertices
+                .iter()
+                .map(|vertex| vertex.position)
+                .sum::<Vec3>()
+                / 4.0;
+            assert_eq!(face_center - center, side.normal() * 0.5, "{side:?}");
+            for vertex in &mesh.vertices {
+                assert_eq!(vertex.normal, side.normal().extend(3.0), "{side:?}");
+                // Every corner lies in the plane the normal describes.
+                assert_eq!((vertex.position - face_center).dot(side.normal()), 0.0);
+            }
+        }
+    }
 
-pub fn mesh_right(mesh: &mut BlockMesh, pos: Vec3, texture: Texture2D) {
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 1.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 0.0), vec2(0.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 0.0), vec2(1.0, 0.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 0.0, 1.0), vec2(0.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 1.0), vec2(1.0, 1.0)));
-    mesh.vertices
-        .push(vert(pos + vec3(0.0, 1.0, 0.0), vec2(1.0, 0.0)));
-
-    for _ in 0..6 {
-        mesh.indices.push(mesh.idx_counter as u16);
-        mesh.idx_counter += 1;
+    #[test]
+    fn normals_survive_larger_faces() {
+        let mut mesh = BlockMesh::new();
+        mesh_left(&mut mesh, Vec3::ZERO, vec3(1.0, 4.0, 3.0), tile(), NO_AO);
+        assert!(mesh
+            .vertices
+            .iter()
+            .all(|vertex| vertex.normal.truncate() == Vec3::X && vertex.position.x == 1.0));
     }
 
-    mesh.texture = Some(texture);
-}
\ No newline at end of file
+    #[test]
+    fn shade_is_baked_into_vertex_colours() {
+        let brightness = |side| {
+            let mut mesh = BlockMesh::new();
+            mesh_side(&mut mesh, side, Vec3::ZERO, Vec3::ONE, tile(), NO_AO);
+            let color = mesh.vertices[0].color;
+            assert!(mesh.vertices.iter().all(|vertex| vertex.color == color));
+            assert_eq!(color[0], color[1]);
+            assert_eq!(color[3], 255);
+            color[0]
+        };
+        let top = brightness(BlockSides::Top);
+        let bottom = brightness(BlockSides::Bottom);
+        assert_eq!(top, 255);
+        for side in [
+            BlockSides::Front,
+            BlockSides::Back,
+            BlockSides::Left,
+            BlockSides::Right,
+        ] {
+            assert!(bottom < brightness(side) && brightness(side) < top);
+        }
+    }
+
+    #[test]
+    fn ao_darkens_vertices_and_picks_the_bright_diagonal() {
+        let mut mesh = BlockMesh::new();
+        mesh_top(&mut mesh, Vec3::ZERO, Vec3::ONE, tile(), [3, 3, 3, 3]);
+        mesh_top(&mut mesh, Vec3::ZERO, Vec3::ONE, tile(), [0, 3, 3, 3]);
+        mesh_top(&mut mesh, Vec3::ZERO, Vec3::ONE, tile(), [3, 0, 3, 3]);
+
+        assert_eq!(mesh.indices[..6], [0, 1, 2, 0, 3, 2]);
+        // The dark corner 0 stays out of the split, so it only shades one triangle.
+        assert_eq!(mesh.indices[6..12], [5, 6, 7, 5, 4, 7]);
+        assert_eq!(mesh.indices[12..], [8, 9, 10, 8, 11, 10]);
+
+        let brightness: Vec<u8> = mesh.vertices[4..8]
+            .iter()
+            .map(|vertex| vertex.color[0])
+            .collect();
+        assert!(brightness[0] < brightness[1]);
+        assert_eq!(brightness[1], 255);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::systems::world::{world_to_chunk, World};
+use macroquad::prelude::*;
+
+/// How far around the player chunks are kept loaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamSettings {
+    /// Horizontal radius, in chunks, of the loaded cylinder around the player.
+    pub render_distance: i32,
+    /// Chunks loaded above and below the player's chunk.
+    pub vertical_distance: i32,
+    /// Chunks generated per call to `ChunkStreamer::load`.
+    pub loads_per_frame: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            render_distance: 6,
+            vertical_distance: 3,
+            loads_per_frame: 4,
+        }
+    }
+}
+
+/// Chunks stay loaded this many chunks past the render distance, so walking back
+/// and forth over a chunk border does not reload the same chunks every time.
+const UNLOAD_MARGIN: i32 = 1;
+
+/// Squared distance between two chunk coordinates, for ordering.
+fn distance_squared(a: IVec3, b: IVec3) -> i32 {
+    (a - b).length_squared()
+}
+
+/// Decides which chunks around the player should be loaded, queues the missing
+/// ones nearest first and evicts the ones left too far behind.
+///
+/// Generation itself is up to the caller, so the streamer can be driven without
+/// a window or any terrain.
+pub struct ChunkStreamer {
+    settings: StreamSettings,
+    center: Option<IVec3>,
+    /// Chunks waiting to be generated, farthest first so the nearest one pops off
+    /// the end.
+    queue: Vec<IVec3>,
+}
+
+impl ChunkStreamer {
+    pub fn new(settings: StreamSettings) -> Self {
+        Self {
+            settings,
+            center: None,
+            queue: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> StreamSettings {
+        self.settings
+    }
+
+    /// Changes how far chunks are loaded. The queue and the loaded chunks are
+    /// brought in line on the next `update`.
+    pub fn set_settings(&mut self, settings: StreamSettings) {
+        if self.settings != settings {
+            self.settings = settings;
+            self.center = None;
+        }
+    }
+
+    /// Chunks waiting to be generated.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the chunk at `coord` is still in range, e.g. when it arrives from
+    /// a worker thread after the player moved on.
+    pub fn is_wanted(&self, coord: IVec3) -> bool {
+        self.center
+            .is_none_or(|center| self.in_range(center, coord, UNLOAD_MARGIN))
+    }
+
+    fn in_range(&self, center: IVec3, coord: IVec3, margin: i32) -> bool {
+        let offset = coord - center;
+        let radius = self.settings.render_distance + margin;
+        offset.x * offset.x + offset.z * offset.z <= radius * radius
+            && offset.y.abs() <= self.settings.vertical_distance + margin
+    }
+
+    /// Follows the player to `position`. Whenever they enter another chunk the
+    /// queue is rebuilt, and loaded chunks out of range are removed from `world`.
+    /// Returns the removed chunk coordinates, farthest first.
+    pub fn update(&mut self, position: Vec3, world: &mut World) -> Vec<IVec3> {
+        let center = world_to_chunk(position.floor().as_ivec3()).0;
+        if self.center == Some(center) {
+            return Vec::new();
+        }
+        self.center = Some(center);
+
+        let mut evicted: Vec<IVec3> = world
+            .coords()
+            .filter(|&coord| !self.in_range(center, coord, UNLOAD_MARGIN))
+            .collect();
+        evicted.sort_by_key(|&coord| -distance_squared(coord, center));
+        for &coord in &evicted {
+            world.remove(coord);
+        }
+
+        let (radius, height) = (
+            self.settings.render_distance,
+            self.settings.vertical_distance,
+        );
+        self.queue.clear();
+        for x in -radius..=radius {
+            for y in -height..=height {
+                for z in -radius..=radius {
+                    let coord = center + ivec3(x, y, z);
+                    if self.in_range(center, coord, 0) && world.chunk(coord).is_none() {
+                        self.queue.push(coord);
+                    }
+                }
+            }
+        }
+        self.queue
+            .sort_by_key(|&coord| -distance_squared(coord, center));
+        evicted
+    }
+
+    /// Generates up to `loads_per_frame` queued chunks, nearest first.
+    /// `generate` is expected to insert the chunk at the coordinate into `world`,
+    /// or to start generating it so it can be inserted later.
+    pub fn load(&mut self, world: &mut World, mut generate: impl FnMut(&mut World, IVec3)) {
+        for _ in 0..self.settings.loads_per_frame {
+            let Some(coord) = self.queue.pop() else {
+                break;
+            };
+            if world.chunk(coord).is_none() {
+                generate(world, coord);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::chunks::Chunk;
+
+    fn settings() -> StreamSettings {
+        StreamSettings {
+            render_distance: 3,
+            vertical_distance: 1,
+            loads_per_frame: 5,
+        }
+    }
+
+    fn empty_chunk(world: &mut World, coord: IVec3) {
+        world.insert(Chunk::new(coord));
+    }
+
+    /// Every chunk the settings put in range of `center`.
+    fn expected(center: IVec3, settings: StreamSettings) -> Vec<IVec3> {
+        let streamer = ChunkStreamer::new(settings);
+        let mut coords = Vec::new();
+        let r = settings.render_distance;
+        let h = settings.vertical_distance;
+        for x in -r..=r {
+            for y in -h..=h {
+                for z in -r..=r {
+                    let coord = center + ivec3(x, y, z);
+                    if streamer.in_range(center, coord, 0) {
+                        coords.push(coord);
+                    }
+                }
+            }
+        }
+        coords
+    }
+
+    fn sorted(coords: impl Iterator<Item = IVec3>) -> Vec<IVec3> {
+        let mut coords: Vec<IVec3> = coords.collect();
+        coords.sort_by_key(|coord| coord.to_array());
+        coords
+    }
+
+    #[test]
+    fn loads_nearest_first() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings());
+        let position = vec3(40.0, 20.0, -8.0);
+        let center = ivec3(2, 1, -1);
+        streamer.update(position, &mut world);
+        assert_eq!(streamer.queued(), expected(center, settings()).len());
+
+        let mut order = Vec::new();
+        while streamer.queued() > 0 {
+            streamer.load(&mut world, |world, coord| {
+                order.push(coord);
+                empty_chunk(world, coord);
+            });
+        }
+        assert_eq!(order[0], center);
+        let distances: Vec<i32> = order
+            .iter()
+            .map(|&coord| distance_squared(coord, center))
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(
+            sorted(world.coords()),
+            sorted(expected(center, settings()).into_iter())
+        );
+    }
+
+    #[test]
+    fn load_respects_the_frame_budget() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings());
+        streamer.update(Vec3::ZERO, &mut world);
+        let queued = streamer.queued();
+        streamer.load(&mut world, empty_chunk);
+        assert_eq!(world.chunk_count(), 5);
+        assert_eq!(streamer.queued(), queued - 5);
+    }
+
+    #[test]
+    fn follows_a_simulated_walk() {
+        let settings = settings();
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings);
+        let mut evicted = Vec::new();
+
+        // Walk 10 chunks along x, then turn and climb diagonally, a block per frame.
+        let mut position = vec3(0.5, 20.0, 0.5);
+        let path = (0..160)
+            .map(|_| vec3(1.0, 0.0, 0.0))
+            .chain((0..96).map(|_| vec3(0.0, 0.25, -1.0)));
+        for step in path {
+            position += step;
+            evicted.extend(streamer.update(position, &mut world));
+            streamer.load(&mut world, empty_chunk);
+
+            let center = world_to_chunk(position.floor().as_ivec3()).0;
+            // Nothing stays loaded past the render distance and its margin.
+            assert!(world
+                .coords()
+                .all(|coord| streamer.in_range(center, coord, UNLOAD_MARGIN)));
+        }
+        while streamer.queued() > 0 {
+            streamer.load(&mut world, empty_chunk);
+        }
+
+        let center = world_to_chunk(position.floor().as_ivec3()).0;
+        let loaded = sorted(world.coords());
+        for coord in expected(center, settings) {
+            assert!(loaded.contains(&coord), "{coord} missing");
+        }
+        // The start of the walk is long gone.
+        assert!(world.chunk(ivec3(0, 1, 0)).is_none());
+        assert!(evicted.contains(&ivec3(0, 1, 0)));
+    }
+
+    #[test]
+    fn evicts_farthest_first() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings());
+        for coord in [
+            ivec3(-5, 0, 0),
+            ivec3(9, 0, 0),
+            ivec3(0, 0, 7),
+            ivec3(1, 0, 0),
+        ] {
+            empty_chunk(&mut world, coord);
+        }
+        let evicted = streamer.update(vec3(1.0, 1.0, 1.0), &mut world);
+        assert_eq!(evicted, [ivec3(9, 0, 0), ivec3(0, 0, 7), ivec3(-5, 0, 0)]);
+        assert_eq!(sorted(world.coords()), [ivec3(1, 0, 0)]);
+        // The chunk already loaded is not queued again.
+        assert_eq!(
+            streamer.queued(),
+            expected(IVec3::ZERO, settings()).len() - 1
+        );
+    }
+
+    #[test]
+    fn changing_settings_requeues() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings());
+        streamer.update(Vec3::ZERO, &mut world);
+        let small = streamer.queued();
+        streamer.set_settings(StreamSettings {
+            render_distance: 5,
+            ..settings()
+        });
+        assert!(streamer.update(Vec3::ZERO, &mut world).is_empty());
+        assert!(streamer.queued() > small);
+    }
+
+    #[test]
+    fn chunks_behind_the_player_are_unwanted() {
+        let mut world = World::new();
+        let mut streamer = ChunkStreamer::new(settings());
+        assert!(streamer.is_wanted(ivec3(100, 0, 0)));
+        streamer.update(Vec3::ZERO, &mut world);
+        assert!(streamer.is_wanted(ivec3(4, 0, 0)));
+        streamer.update(vec3(-160.0, 0.0, 0.0), &mut world);
+        assert!(!streamer.is_wanted(ivec3(4, 0, 0)));
+    }
+}
@@ -0,0 +1,524 @@
+use crate::{
+    systems::{
+        biomes::Biome,
+        blocks::BlockType,
+        chunks::Chunk,
+        noise::{below, chance, chunk_seed},
+        registry::{BlockRegistry, RegistryError},
+        terrain::TerrainGenerator,
+        world::{world_to_chunk, World},
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+/// Salt of the structure pass in `chunk_seed`.
+const STRUCTURE_SALT: u64 = 0x7374_7275_6374;
+
+/// Side of the square cells structures are scattered over. Every cell holds at
+/// most one structure, started at least `MARGIN` blocks from the cell border.
+const CELL: i32 = 8;
+const MARGIN: i32 = 2;
+/// Furthest a structure reaches from its start column along x or z. Keeping it
+/// within `MARGIN` means two structures never touch the same block, so the
+/// order their blocks are written in never matters.
+const REACH: i32 = MARGIN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Structure {
+    Tree,
+    Boulder,
+    Ruin,
+}
+
+impl Structure {
+    /// What the decorations of `biome` look like.
+    pub fn for_biome(biome: Biome) -> Self {
+        match biome {
+            Biome::Plains | Biome::Tundra => Structure::Tree,
+            Biome::Desert => Structure::Ruin,
+            Biome::Mountains => Structure::Boulder,
+        }
+    }
+}
+
+/// Blocks structures are built from, looked up by name once.
+#[derive(Clone, Copy, Debug)]
+pub struct StructureBlocks {
+    pub log: BlockType,
+    pub leaves: BlockType,
+    pub stone: BlockType,
+    pub cobblestone: BlockType,
+}
+
+impl StructureBlocks {
+    pub fn from_registry(registry: &BlockRegistry) -> Result<Self, RegistryError> {
+        Ok(Self {
+            log: registry.require("log")?,
+            leaves: registry.require("leaves")?,
+            stone: registry.require("stone")?,
+            cobblestone: registry.require("cobblestone")?,
+        })
+    }
+
+    /// Blocks of every structure whose start, the block above the surface of its
+    /// column, lies in the chunk at `coord`. Depends only on the seed and the
+    /// terrain's heightmap, never on other chunks.
+    pub fn structures(&self, generator: &TerrainGenerator, coord: IVec3) -> Vec<BlockWrite> {
+        let mut writes = Vec::new();
+        let origin = coord * CHUNK_SIZE_16;
+        let cells = CHUNK_SIZE_16 / CELL;
+        for cell_z in 0..cells {
+            for cell_x in 0..cells {
+                let corner = origin + ivec3(cell_x, 0, cell_z) * CELL;
+                let cell = ivec3(corner.x / CELL, 0, corner.z / CELL);
+                let mut state = chunk_seed(generator.seed(), cell, STRUCTURE_SALT);
+                let span = (CELL - 2 * MARGIN) as u32;
+                let x = corner.x + MARGIN + below(&mut state, span);
+                let z = corner.z + MARGIN + below(&mut state, span);
+                let start = ivec3(x, generator.height(x, z) + 1, z);
+                if !(origin.y..origin.y + CHUNK_SIZE_16).contains(&start.y) {
+                    continue;
+                }
+
+                let biome = generator.biome(x, z).unwrap_or(Biome::Plains);
+                let density = biome.settings().decoration_density;
+                if chance(&mut state) >= density * (CELL * CELL) as f64 {
+                    continue;
+                }
+                match Structure::for_biome(biome) {
+                    Structure::Tree => self.tree(start, &mut state, &mut writes),
+                    Structure::Boulder => self.boulder(start, &mut state, &mut writes),
+                    Structure::Ruin => self.ruin(start, &mut state, &mut writes),
+                }
+            }
+        }
+        writes
+    }
+
+    /// A trunk of four to six logs under a rounded crown of leaves.
+    fn tree(&self, start: IVec3, state: &mut u64, writes: &mut Vec<BlockWrite>) {
+        let height = 4 + below(state, 3);
+        for y in 0..height {
+            writes.push(BlockWrite {
+                pos: start + ivec3(0, y, 0),
+                block: self.log,
+            });
+        }
+        let top = height - 1;
+        for y in top - 1..=top + 2 {
+            let radius = if y < top + 1 { REACH } else { 1 };
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    let corner = x.abs() == radius && z.abs() == radius;
+                    // The lower layers lose some of their corners at random, the
+                    // upper ones all of them.
+                    let trunk = x == 0 && z == 0 && y <= top;
+                    if trunk || (corner && (radius == 1 || chance(state) < 0.5)) {
+                        continue;
+                    }
+                    writes.push(BlockWrite {
+                        pos: start + ivec3(x, y, z),
+                        block: self.leaves,
+                    });
+                }
+            }
+        }
+    }
+
+    /// A lump of stone half sunk into the ground.
+    fn boulder(&self, start: IVec3, state: &mut u64, writes: &mut Vec<BlockWrite>) {
+        let radius = 1 + below(state, REACH as u32);
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    if x * x + y * y + z * z <= radius * radius + 1 {
+                        writes.push(BlockWrite {
+                            pos: start + ivec3(x, y, z),
+                            block: self.stone,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// The crumbling cobblestone walls of a small square room.
+    fn ruin(&self, start: IVec3, state: &mut u64, writes: &mut Vec<BlockWrite>) {
+        for z in -REACH..=REACH {
+            for x in -REACH..=REACH {
+                if x.abs() < REACH && z.abs() < REACH {
+                    continue;
+                }
+                for y in 0..below(state, 4) {
+                    writes.push(BlockWrite {
+                        pos: start + ivec3(x, y, z),
+                        block: self.cobblestone,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// One block of a structure, at a world position. Structures only ever fill
+/// air, so they settle onto the terrain instead of cutting into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockWrite {
+    pub pos: IVec3,
+    pub block: BlockType,
+}
+
+/// Fills the block at `local` with `block` if it is air.
+fn fill_air(chunk: &mut Chunk, local: IVec3, block: BlockType) {
+    if chunk.get(local.x, local.y, local.z) == BlockType::AIR {
+        chunk.set(local.x, local.y, local.z, block);
+    }
+}
+
+/// Inserts generated chunks into the world along with their structures.
+///
+/// Structures may reach into neighbouring chunks. Blocks for chunks already in
+/// the world are written straight into them, and blocks for chunks that are not
+/// loaded are buffered as pending writes until those chunks are generated. So
+/// the world comes out the same whatever order its chunks are generated in.
+#[derive(Debug, Default)]
+pub struct StructurePlacer {
+    /// Blocks waiting for chunks that are not loaded, by chunk.
+    pending: HashMap<IVec3, Vec<BlockWrite>>,
+    /// Blocks the structures of each loaded chunk put outside of it, so they can
+    /// be queued again when the chunk they landed in is unloaded.
+    spilled: HashMap<IVec3, Vec<BlockWrite>>,
+}
+
+impl StructurePlacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a freshly generated `chunk` into `world` along with the blocks
+    /// waiting for it and `writes`, the blocks of the structures starting in it
+    /// from `StructureBlocks::structures`.
+    pub fn place(&mut self, world: &mut World, mut chunk: Chunk, writes: Vec<BlockWrite>) {
+        let coord = chunk.position();
+        for write in self.pending.remove(&coord).unwrap_or_default() {
+            fill_air(&mut chunk, world_to_chunk(write.pos).1, write.block);
+        }
+        let mut spilled = Vec::new();
+        for write in writes {
+            let (target, local) = world_to_chunk(write.pos);
+            if target == coord {
+                fill_air(&mut chunk, local, write.block);
+                continue;
+            }
+            if world.chunk(target).is_none() {
+                self.pending.entry(target).or_default().push(write);
+            } else if world.get_block(write.pos) == BlockType::AIR {
+                world.set_block(write.pos, write.block);
+            }
+            spilled.push(write);
+        }
+        self.spilled.insert(coord, spilled);
+        world.insert(chunk);
+    }
+
+    /// Forgets the chunk at `coord`, which has just been removed from `world`.
+    ///
+    /// Blocks its structures left waiting for other chunks are dropped, since
+    /// they come back when it is generated again, and the blocks its loaded
+    /// neighbours put into it are queued again. So only chunks next to loaded
+    /// ones ever have blocks waiting.
+    pub fn unload(&mut self, world: &World, coord: IVec3) {
+        for write in self.spilled.remove(&coord).unwrap_or_default() {
+            let target = world_to_chunk(write.pos).0;
+            if let Some(pending) = self.pending.get_mut(&target) {
+                pending.retain(|&waiting| waiting != write);
+                if pending.is_empty() {
+                    self.pending.remove(&target);
+                }
+            }
+        }
+
+        let mut pending = Vec::new();
+        // Structures reach less than a chunk from their start, so only direct
+        // neighbours can reach into it.
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let neighbour = coord + ivec3(x, y, z);
+                    if neighbour == coord || world.chunk(neighbour).is_none() {
+                        continue;
+                    }
+                    let Some(spilled) = self.spilled.get(&neighbour) else {
+                        continue;
+                    };
+                    pending.extend(
+                        spilled
+                            .iter()
+                            .filter(|write| world_to_chunk(write.pos).0 == coord),
+                    );
+                }
+            }
+        }
+        if pending.is_empty() {
+            self.pending.remove(&coord);
+        } else {
+            self.pending.insert(coord, pending);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{registry::test_registry, terrain::TerrainBlocks};
+
+    fn setup(seed: u64) -> (TerrainGenerator, StructureBlocks) {
+        let registry = test_registry();
+        let generator =
+            TerrainGenerator::new(seed, TerrainBlocks::from_registry(&registry).unwrap());
+        let structures = StructureBlocks::from_registry(&registry).unwrap();
+        (generator, structures)
+    }
+
+    /// Chunk coordinates of a 4 x 2 x 4 area around the surface.
+    fn area() -> Vec<IVec3> {
+        let mut coords = Vec::new();
+        for x in -2..2 {
+            for z in -2..2 {
+                for y in 1..3 {
+                    coords.push(ivec3(x, y, z));
+                }
+            }
+        }
+        coords
+    }
+
+    /// A world filled the way the game fills it: each chunk is generated along
+    /// with the structures starting in it, then placed.
+    struct Game {
+        generator: TerrainGenerator,
+        structures: StructureBlocks,
+        placer: StructurePlacer,
+        world: World,
+    }
+
+    impl Game {
+        fn new(seed: u64) -> Self {
+            let (generator, structures) = setup(seed);
+            Self {
+                generator,
+                structures,
+                placer: StructurePlacer::new(),
+                world: World::new(),
+            }
+        }
+
+        fn generate(&mut self, coord: IVec3) {
+            let chunk = self.generator.generate(coord);
+            let writes = self.structures.structures(&self.generator, coord);
+            self.placer.place(&mut self.world, chunk, writes);
+        }
+
+        /// Unloads all of `coords` at once, as `ChunkStreamer::update` does.
+        fn unload(&mut self, coords: &[IVec3]) {
+            for &coord in coords {
+                self.world.remove(coord);
+            }
+            for &coord in coords {
+                self.placer.unload(&self.world, coord);
+            }
+        }
+    }
+
+    fn build(seed: u64, order: &[IVec3]) -> World {
+        let mut game = Game::new(seed);
+        for &coord in order {
+            game.generate(coord);
+        }
+        game.world
+    }
+
+    #[test]
+    fn generation_order_does_not_matter() {
+        let forward = area();
+        let mut backward = forward.clone();
+        backward.reverse();
+        // Steps of 7 through 32 chunks visit every one, rarely two neighbours in
+        // a row.
+        let shuffled: Vec<IVec3> = (0..forward.len())
+            .map(|i| forward[(i * 7) % forward.len()])
+            .collect();
+
+        for seed in [3, 21] {
+            let worlds = [
+                build(seed, &forward),
+                build(seed, &backward),
+                build(seed, &shuffled),
+            ];
+            for x in -32..32 {
+                for z in -32..32 {
+                    for y in 16..48 {
+                        let pos = ivec3(x, y, z);
+                        let block = worlds[0].get_block(pos);
+                        assert_eq!(worlds[1].get_block(pos), block, "{pos}");
+                        assert_eq!(worlds[2].get_block(pos), block, "{pos}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn structures_reach_into_chunks_generated_later() {
+        let mut game = Game::new(3);
+        let spilling = area()
+            .into_iter()
+            .find(|&coord| {
+                game.structures
+                    .structures(&game.generator, coord)
+                    .iter()
+                    .any(|write| world_to_chunk(write.pos).0 != coord)
+            })
+            .expect("no structure crosses a chunk border");
+
+        game.generate(spilling);
+        let writes: Vec<BlockWrite> = game
+            .structures
+            .structures(&game.generator, spilling)
+            .into_iter()
+            .filter(|write| world_to_chunk(write.pos).0 != spilling)
+            .collect();
+        let target = world_to_chunk(writes[0].pos).0;
+        let writes: Vec<BlockWrite> = writes
+            .into_iter()
+            .filter(|write| world_to_chunk(write.pos).0 == target)
+            .collect();
+        assert!(game.world.chunk(target).is_none());
+
+        game.generate(target);
+        let terrain = game.generator.generate(target);
+        for write in writes {
+            let local = world_to_chunk(write.pos).1;
+            let expected = match terrain.get(local.x, local.y, local.z) {
+                BlockType::AIR => write.block,
+                ground => ground,
+            };
+            assert_eq!(game.world.get_block(write.pos), expected, "{}", write.pos);
+        }
+    }
+
+    #[test]
+    fn unloaded_chunks_get_their_structures_back() {
+        let mut game = Game::new(3);
+        for coord in area() {
+            game.generate(coord);
+        }
+        let snapshot = |world: &World, coord: IVec3| {
+            (0..CHUNK_SIZE_16.pow(3))
+                .map(|i| {
+                    world.get_block(coord * CHUNK_SIZE_16 + ivec3(i % 16, i / 256, i / 16 % 16))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (coord, neighbour) in [
+            (ivec3(0, 2, 0), ivec3(-1, 2, 0)),
+            (ivec3(-1, 1, -1), ivec3(-1, 2, -1)),
+            (ivec3(0, 1, -1), ivec3(1, 2, -2)),
+        ] {
+            let before = [
+                snapshot(&game.world, coord),
+                snapshot(&game.world, neighbour),
+            ];
+            // Unload just the chunk, then the chunk and a neighbour together.
+            game.unload(&[coord]);
+            game.generate(coord);
+            assert_eq!(snapshot(&game.world, coord), before[0], "{coord}");
+
+            game.unload(&[coord, neighbour]);
+            for loaded in [neighbour, coord] {
+                game.generate(loaded);
+            }
+            assert_eq!(snapshot(&game.world, coord), before[0], "{coord}");
+            assert_eq!(snapshot(&game.world, neighbour), before[1], "{neighbour}");
+        }
+    }
+
+    #[test]
+    fn only_chunks_next_to_loaded_ones_have_blocks_waiting() {
+        let mut game = Game::new(3);
+        let area = area();
+        for &coord in &area {
+            game.generate(coord);
+        }
+        let waiting = |game: &Game| {
+            let mut coords: Vec<IVec3> = game.placer.pending.keys().copied().collect();
+            coords.sort_by_key(|coord| coord.to_array());
+            coords
+        };
+        let near_loaded = |game: &Game, coord: IVec3| {
+            game.world.chunk(coord).is_none()
+                && game
+                    .world
+                    .coords()
+                    .any(|loaded| (loaded - coord).abs().max_element() <= 1)
+        };
+        let before = waiting(&game);
+        assert!(before.iter().all(|&coord| near_loaded(&game, coord)));
+
+        // Unloading the top layer leaves the trees below waiting on it.
+        let gone: Vec<IVec3> = area.iter().copied().filter(|coord| coord.y == 2).collect();
+        game.unload(&gone);
+        let waiting_for_half = waiting(&game);
+        assert!(waiting_for_half.iter().any(|coord| gone.contains(coord)));
+        assert!(waiting_for_half
+            .iter()
+            .all(|&coord| near_loaded(&game, coord)));
+        // Bringing it back waits on the same chunks as before.
+        for &coord in &gone {
+            game.generate(coord);
+        }
+        assert_eq!(waiting(&game), before);
+
+        game.unload(&area);
+        assert!(game.placer.pending.is_empty());
+        assert!(game.placer.spilled.is_empty());
+    }
+
+    #[test]
+    fn trees_stand_on_the_surface() {
+        let (generator, structures) = setup(8);
+        let mut trees = 0;
+        for coord in area() {
+            for write in structures.structures(&generator, coord) {
+                let below = write.pos - IVec3::Y;
+                if write.block == structures.log && generator.height(below.x, below.z) == below.y {
+                    trees += 1;
+                }
+            }
+        }
+        // About 0.64 trees per 8 x 8 cell over 64 cells.
+        assert!((20..60).contains(&trees), "{trees}");
+    }
+
+    #[test]
+    fn no_block_is_written_twice() {
+        for seed in [13, 14] {
+            let (generator, structures) = setup(seed);
+            let generator = generator.with_biomes();
+            let mut seen = std::collections::HashSet::new();
+            for x in -6..6 {
+                for z in -6..6 {
+                    for y in 0..6 {
+                        for write in structures.structures(&generator, ivec3(x, y, z)) {
+                            assert!(seen.insert(write.pos), "{} written twice", write.pos);
+                        }
+                    }
+                }
+            }
+            assert!(!seen.is_empty());
+        }
+    }
+}
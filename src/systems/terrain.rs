@@ -0,0 +1,425 @@
+use crate::{
+    systems::{
+        biomes::{Biome, BiomeMap},
+        blocks::BlockType,
+        caves::{CaveCarver, CaveSettings},
+        chunks::Chunk,
+        noise::{Fbm, GradientNoise},
+        ores::{place_ores, OreSettings},
+        registry::{BlockRegistry, RegistryError},
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+/// World y the heightmap is centred on.
+pub const BASE_HEIGHT: i32 = 32;
+/// Largest distance the surface strays above or below `BASE_HEIGHT` without
+/// biomes.
+pub const HEIGHT_RANGE: f64 = 24.0;
+/// Filler blocks, such as dirt, between the surface block and the stone below.
+pub const FILLER_DEPTH: i32 = 3;
+
+const HEIGHTMAP: Fbm = Fbm {
+    octaves: 5,
+    frequency: 1.0 / 128.0,
+    lacunarity: 2.0,
+    gain: 0.5,
+};
+
+/// Top layers of a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Surface {
+    pub top: BlockType,
+    pub filler: BlockType,
+}
+
+/// Blocks the generator places, looked up by name once.
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainBlocks {
+    pub stone: BlockType,
+    pub dirt: BlockType,
+    pub grass: BlockType,
+    /// Surface of every biome, in the order of `Biome::ALL`.
+    pub biomes: [Surface; 4],
+}
+
+impl TerrainBlocks {
+    pub fn from_registry(registry: &BlockRegistry) -> Result<Self, RegistryError> {
+        let mut biomes = [Surface {
+            top: BlockType::AIR,
+            filler: BlockType::AIR,
+        }; 4];
+        for (surface, biome) in biomes.iter_mut().zip(Biome::ALL) {
+            let settings = biome.settings();
+            surface.top = registry.require(settings.surface)?;
+            surface.filler = registry.require(settings.filler)?;
+        }
+        Ok(Self {
+            stone: registry.require("stone")?,
+            dirt: registry.require("dirt")?,
+            grass: registry.require("grass")?,
+            biomes,
+        })
+    }
+
+    pub fn surface(&self, biome: Biome) -> Surface {
+        self.biomes[biome as usize]
+    }
+}
+
+/// Surface height and top layers of one column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Column {
+    height: i32,
+    surface: Surface,
+}
+
+/// Builds chunks from a world seed. The same seed and chunk coordinate always
+/// produce the same blocks, whatever order chunks are generated in.
+#[derive(Clone, Debug)]
+pub struct TerrainGenerator {
+    seed: u64,
+    noise: GradientNoise,
+    blocks: TerrainBlocks,
+    caves: Option<CaveCarver>,
+    biomes: Option<BiomeMap>,
+    ores: Vec<OreSettings>,
+}
+
+impl TerrainGenerator {
+    /// Plain grass covered heightmap terrain without caves.
+    pub fn new(seed: u64, blocks: TerrainBlocks) -> Self {
+        Self {
+            seed,
+            noise: GradientNoise::new(seed),
+            blocks,
+            caves: None,
+            biomes: None,
+            ores: Vec::new(),
+        }
+    }
+
+    /// Picks the surface blocks and height of every column from the biome its
+    /// climate selects.
+    pub fn with_biomes(mut self) -> Self {
+        self.biomes = Some(BiomeMap::new(self.seed));
+        self
+    }
+
+    /// Carves tunnels out of the terrain below the surface.
+    pub fn with_caves(mut self, settings: CaveSettings) -> Self {
+        self.caves = Some(CaveCarver::new(self.seed, settings));
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Biome of the column at (x, z), if biomes are enabled.
+    pub fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        Some(self.biomes.as_ref()?.biome(x, z))
+    }
+
+    /// Grows veins of `ores` in the rock, after the caves are carved.
+    pub fn with_ores(mut self, ores: Vec<OreSettings>) -> Self {
+        self.ores = ores;
+        self
+    }
+
+    /// World y of the topmost solid block of the column at (x, z).
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        self.column(x, z).height
+    }
+
+    fn column(&self, x: i32, z: i32) -> Column {
+        let sample = HEIGHTMAP.sample(&self.noise, x as f64, z as f64);
+        match &self.biomes {
+            None => Column {
+                height: BASE_HEIGHT + (sample * HEIGHT_RANGE).round() as i32,
+                surface: Surface {
+                    top: self.blocks.grass,
+                    filler: self.blocks.dirt,
+                },
+            },
+            Some(biomes) => {
+                let shape = biomes.shape(x, z);
+                let offset = shape.height_offset + sample * shape.height_scale;
+                Column {
+                    height: BASE_HEIGHT + offset.round() as i32,
+                    surface: self.blocks.surface(biomes.biome(x, z)),
+                }
+            }
+        }
+    }
+
+    /// Block at world height `y` in `column`.
+    fn layer(&self, y: i32, column: Column) -> BlockType {
+        if y > column.height {
+            BlockType::AIR
+        } else if y == column.height {
+            column.surface.top
+        } else if y >= column.height - FILLER_DEPTH {
+            column.surface.filler
+        } else {
+            self.blocks.stone
+        }
+    }
+
+    pub fn generate(&self, coord: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(coord);
+        let origin = chunk.origin();
+        for z in 0..CHUNK_SIZE_16 {
+            for x in 0..CHUNK_SIZE_16 {
+                let column = self.column(origin.x + x, origin.z + z);
+                for y in 0..CHUNK_SIZE_16 {
+                    let block_type = self.layer(origin.y + y, column);
+                    if block_type == BlockType::AIR {
+                        continue;
+                    }
+                    if let Some(caves) = &self.caves {
+                        if caves.carves(origin + ivec3(x, y, z), column.height) {
+                            continue;
+                        }
+                    }
+                    chunk.set(x, y, z, block_type);
+                }
+            }
+        }
+        place_ores(&mut chunk, self.seed, &self.ores);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{ores::default_ores, registry::test_registry};
+
+    fn generator(seed: u64) -> TerrainGenerator {
+        TerrainGenerator::new(
+            seed,
+            TerrainBlocks::from_registry(&test_registry()).unwrap(),
+        )
+    }
+
+    /// FNV-1a over the little-endian block ids in storage order.
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        let mut hash = 0xCBF2_9CE4_8422_2325u64;
+        for y in 0..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                for x in 0..CHUNK_SIZE_16 {
+                    for byte in chunk.get(x, y, z).0.to_le_bytes() {
+                        hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+                    }
+                }
+            }
+        }
+        hash
+    }
+
+    /// Pinned chunk contents. If terrain generation changes on purpose, update
+    /// these from the values in the failure messages.
+    #[test]
+    fn golden_hashes() {
+        let cases = [
+            (0, ivec3(0, 2, 0), 0x55d2_b411_6b4a_4f2c),
+            (0, ivec3(-3, 1, 5), 0xd11e_13f6_9082_5a4e),
+            (42, ivec3(0, 2, 0), 0x2b7a_c720_4ea5_f89e),
+            (42, ivec3(7, 3, -2), 0xb9d1_03fd_6854_a325),
+            (0xDEAD_BEEF, ivec3(-1, 2, -1), 0xa92a_d6a1_4ff1_f746),
+        ];
+        for (seed, coord, expected) in cases {
+            let hash = chunk_hash(&generator(seed).generate(coord));
+            assert_eq!(hash, expected, "seed {seed}, chunk {coord}: {hash:#x}");
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let a = generator(1234);
+        let b = generator(1234);
+        for coord in [ivec3(0, 2, 0), ivec3(-5, 1, 9), ivec3(3, 3, -3)] {
+            assert_eq!(
+                chunk_hash(&a.generate(coord)),
+                chunk_hash(&b.generate(coord))
+            );
+        }
+        // Generating other chunks first changes nothing.
+        let before = chunk_hash(&a.generate(ivec3(1, 2, 1)));
+        a.generate(ivec3(9, 9, 9));
+        assert_eq!(chunk_hash(&a.generate(ivec3(1, 2, 1))), before);
+    }
+
+    /// Pinned contents of chunks the default cave settings carve into.
+    #[test]
+    fn golden_hashes_with_caves() {
+        let cases = [
+            (0, ivec3(-1, 1, 2), 0xec71_1428_a010_2b8d),
+            (0, ivec3(-1, 0, -1), 0xe57d_a1cc_239a_1bcd),
+            (42, ivec3(-2, 0, 1), 0xaa03_9f7c_bc3e_7dec),
+        ];
+        for (seed, coord, expected) in cases {
+            let generator = generator(seed).with_caves(CaveSettings::default());
+            let hash = chunk_hash(&generator.generate(coord));
+            assert_eq!(hash, expected, "seed {seed}, chunk {coord}: {hash:#x}");
+        }
+    }
+
+    /// Pinned contents of chunks with ore veins, below and at the surface.
+    #[test]
+    fn golden_hashes_with_ores() {
+        let cases = [
+            (0, ivec3(0, 0, 0), 0x4205_8b7a_e48c_b62d),
+            (42, ivec3(3, 1, -4), 0x20b3_8adb_caee_0d5d),
+        ];
+        for (seed, coord, expected) in cases {
+            let generator = generator(seed)
+                .with_caves(CaveSettings::default())
+                .with_ores(default_ores(&test_registry()).unwrap());
+            let hash = chunk_hash(&generator.generate(coord));
+            assert_eq!(hash, expected, "seed {seed}, chunk {coord}: {hash:#x}");
+        }
+    }
+
+    #[test]
+    fn caves_are_seamless_across_chunks() {
+        let generator = generator(8).with_caves(CaveSettings::default());
+        let caves = generator.caves.clone().unwrap();
+        let coords = [
+            ivec3(0, 1, 0),
+            ivec3(1, 1, 0),
+            ivec3(0, 1, 1),
+            ivec3(1, 1, 1),
+        ];
+
+        let forward: Vec<Chunk> = coords.iter().map(|&c| generator.generate(c)).collect();
+        let mut backward: Vec<Chunk> = coords
+            .iter()
+            .rev()
+            .map(|&c| generator.generate(c))
+            .collect();
+        backward.reverse();
+
+        let mut carved = 0;
+        for (chunk, other) in forward.iter().zip(&backward) {
+            assert_eq!(chunk_hash(chunk), chunk_hash(other));
+            let origin = chunk.origin();
+            for x in 0..CHUNK_SIZE_16 {
+                for z in 0..CHUNK_SIZE_16 {
+                    let column = generator.column(origin.x + x, origin.z + z);
+                    for y in 0..CHUNK_SIZE_16 {
+                        let pos = origin + ivec3(x, y, z);
+                        let expected = if caves.carves(pos, column.height) {
+                            carved += 1;
+                            BlockType::AIR
+                        } else {
+                            generator.layer(pos.y, column)
+                        };
+                        assert_eq!(chunk.get(x, y, z), expected, "{pos}");
+                    }
+                }
+            }
+        }
+        assert!(carved > 0);
+    }
+
+    /// Pinned contents of surface chunks in a desert, mountains, tundra and
+    /// plains.
+    #[test]
+    fn golden_hashes_with_biomes() {
+        let cases = [
+            (0, ivec3(-12, 1, -3), 0x7b42_cafe_8bff_65b5),
+            (0, ivec3(-8, 2, 8), 0xdb07_7f98_c621_980d),
+            (42, ivec3(-12, 2, -8), 0x09bd_73da_5586_ae45),
+            (0xDEAD_BEEF, ivec3(-12, 2, -8), 0xdc5e_4788_0a98_8c6d),
+        ];
+        for (seed, coord, expected) in cases {
+            let generator = generator(seed).with_biomes();
+            let hash = chunk_hash(&generator.generate(coord));
+            assert_eq!(hash, expected, "seed {seed}, chunk {coord}: {hash:#x}");
+        }
+    }
+
+    #[test]
+    fn biomes_choose_the_surface_blocks() {
+        assert_eq!(generator(21).biome(0, 0), None);
+        let generator = generator(21).with_biomes();
+        let mut seen = Vec::new();
+        for x in (-3000..3000).step_by(50) {
+            for z in (-3000..3000).step_by(50) {
+                let biome = generator.biome(x, z).unwrap();
+                let column = generator.column(x, z);
+                assert_eq!(column.surface, generator.blocks.surface(biome));
+                assert_eq!(generator.layer(column.height, column), column.surface.top);
+                if !seen.contains(&biome) {
+                    seen.push(biome);
+                }
+            }
+        }
+        assert_eq!(seen.len(), Biome::ALL.len());
+        assert_eq!(
+            generator.blocks.surface(Biome::Plains).top,
+            generator.blocks.grass
+        );
+    }
+
+    #[test]
+    fn seeds_give_different_terrain() {
+        let coord = ivec3(0, 2, 0);
+        assert_ne!(
+            chunk_hash(&generator(1).generate(coord)),
+            chunk_hash(&generator(2).generate(coord))
+        );
+    }
+
+    #[test]
+    fn columns_are_grass_over_dirt_over_stone() {
+        let generator = generator(5);
+        let blocks = generator.blocks;
+        for (x, z) in [(0, 0), (17, -40), (-100, 3)] {
+            let column = generator.column(x, z);
+            let height = column.height;
+            let range = HEIGHT_RANGE as i32;
+            assert!((BASE_HEIGHT - range..=BASE_HEIGHT + range).contains(&height));
+            assert_eq!(generator.layer(height + 1, column), BlockType::AIR);
+            assert_eq!(generator.layer(height, column), blocks.grass);
+            for depth in 1..=FILLER_DEPTH {
+                assert_eq!(generator.layer(height - depth, column), blocks.dirt);
+            }
+            assert_eq!(
+                generator.layer(height - FILLER_DEPTH - 1, column),
+                blocks.stone
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_agree_with_the_heightmap() {
+        let generator = generator(77);
+        let low = generator.generate(ivec3(2, 1, -1));
+        let high = generator.generate(ivec3(2, 2, -1));
+        for (x, z) in [(0, 0), (5, 11), (15, 15)] {
+            let column = generator.column(32 + x, -16 + z);
+            for y in 16..48 {
+                let chunk = if y < 32 { &low } else { &high };
+                assert_eq!(
+                    chunk.get(x, y % 16, z),
+                    generator.layer(y, column),
+                    "column ({x}, {z}) at y {y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn far_above_and_below_the_surface() {
+        let generator = generator(3);
+        let sky = generator.generate(ivec3(0, 8, 0));
+        let deep = generator.generate(ivec3(0, -2, 0));
+        let blocks = generator.blocks;
+        assert!((0..CHUNK_SIZE_16).all(|i| sky.get(i, i, 15 - i) == BlockType::AIR));
+        assert!((0..CHUNK_SIZE_16).all(|i| deep.get(i, 15 - i, i) == blocks.stone));
+    }
+}
@@ -0,0 +1,93 @@
+/// Simulation ticks per second.
+pub const TICK_RATE: f32 = 60.0;
+
+/// Most ticks one frame runs. After a longer stall, such as the window being
+/// dragged, the time left over is dropped instead of being caught up on with a
+/// burst of ticks that would only make the next frame slower still.
+const MAX_TICKS_PER_FRAME: u32 = 10;
+
+/// Turns variable frame times into a whole number of fixed length ticks, and
+/// keeps the time left over for the next frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(rate: f32) -> Self {
+        Self {
+            step: 1.0 / rate,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Length of one tick, in seconds.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds a frame of `frame_time` seconds and returns how many ticks to run
+    /// for it.
+    pub fn advance(&mut self, frame_time: f32) -> u32 {
+        self.accumulator += frame_time.max(0.0);
+        let mut ticks = 0;
+        while self.accumulator >= self.step && ticks < MAX_TICKS_PER_FRAME {
+            self.accumulator -= self.step;
+            ticks += 1;
+        }
+        self.accumulator %= self.step;
+        ticks
+    }
+
+    /// How far the time left over is into the next tick, from 0 to 1, for
+    /// drawing between the last two simulated states.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(TICK_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_a_tick_for_every_step_of_time() {
+        // Powers of two, so the sums are exact.
+        let mut timestep = FixedTimestep::new(64.0);
+        assert_eq!(timestep.advance(1.0 / 128.0), 0);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(1.0 / 128.0), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(3.0 / 128.0), 1);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(3.0 / 64.0), 3);
+        assert_eq!(timestep.alpha(), 0.5);
+    }
+
+    #[test]
+    fn a_second_is_the_tick_rate_at_any_frame_rate() {
+        for fps in [20, 30, 59, 60, 75, 144, 240] {
+            let mut timestep = FixedTimestep::default();
+            let ticks: u32 = (0..fps).map(|_| timestep.advance(1.0 / fps as f32)).sum();
+            // Rounding can leave the last tick a hair short of due.
+            assert!((59..=60).contains(&ticks), "{fps} fps: {ticks}");
+            assert!((0.0..1.0).contains(&timestep.alpha()));
+        }
+    }
+
+    #[test]
+    fn long_stalls_are_not_caught_up_on() {
+        let mut timestep = FixedTimestep::new(64.0);
+        assert_eq!(timestep.advance(5.0 + 1.0 / 256.0), MAX_TICKS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.25);
+        assert_eq!(timestep.advance(1.0 / 64.0), 1);
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+}
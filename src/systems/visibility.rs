@@ -0,0 +1,260 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    systems::{blocks::BlockSides, chunks::Chunk, registry::BlockRegistry},
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+fn face_index(side: BlockSides) -> usize {
+    BlockSides::ALL.iter().position(|&s| s == side).unwrap()
+}
+
+fn opposite(side: BlockSides) -> BlockSides {
+    match side {
+        BlockSides::Top => BlockSides::Bottom,
+        BlockSides::Bottom => BlockSides::Top,
+        BlockSides::Front => BlockSides::Back,
+        BlockSides::Back => BlockSides::Front,
+        BlockSides::Left => BlockSides::Right,
+        BlockSides::Right => BlockSides::Left,
+    }
+}
+
+/// Which pairs of a chunk's six faces are joined by open space inside it: a bit
+/// for every ordered pair of `BlockSides::ALL`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceConnections(u64);
+
+impl FaceConnections {
+    /// Nothing connects, as in a chunk of solid rock.
+    pub const NONE: Self = Self(0);
+    /// Every face sees every other, as in an empty chunk. Also what chunks that
+    /// have not been looked at yet assume, so they are never hidden by mistake.
+    pub const ALL: Self = Self((1 << 36) - 1);
+
+    fn bit(a: BlockSides, b: BlockSides) -> u64 {
+        1 << (face_index(a) * 6 + face_index(b))
+    }
+
+    pub fn connects(self, a: BlockSides, b: BlockSides) -> bool {
+        self.0 & Self::bit(a, b) != 0
+    }
+
+    /// Flood fills the transparent blocks of `chunk` and connects every pair of
+    /// faces that one open region touches.
+    pub fn compute(chunk: &Chunk, registry: &BlockRegistry) -> Self {
+        let size = CHUNK_SIZE_16;
+        let index = |pos: IVec3| ((pos.y * size + pos.z) * size + pos.x) as usize;
+        let mut visited = vec![false; (size * size * size) as usize];
+        let mut connections = Self::NONE;
+        let mut stack = Vec::new();
+
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let start = ivec3(x, y, z);
+                    if visited[index(start)] || !registry.is_transparent(chunk.get(x, y, z)) {
+                        continue;
+                    }
+                    visited[index(start)] = true;
+                    stack.push(start);
+                    // Faces this region reaches.
+                    let mut touched = Vec::with_capacity(6);
+                    while let Some(pos) = stack.pop() {
+                        for side in BlockSides::ALL {
+                            let next = pos + side.normal().as_ivec3();
+                            let inside = next.cmpge(IVec3::ZERO).all()
+                                && next.cmplt(IVec3::splat(size)).all();
+                            if !inside {
+                                if !touched.contains(&side) {
+                                    touched.push(side);
+                                }
+                            } else if !visited[index(next)]
+                                && registry.is_transparent(chunk.get(next.x, next.y, next.z))
+                            {
+                                visited[index(next)] = true;
+                                stack.push(next);
+                            }
+                        }
+                    }
+                    for &a in &touched {
+                        for &b in &touched {
+                            connections.0 |= Self::bit(a, b);
+                        }
+                    }
+                }
+            }
+        }
+        connections
+    }
+}
+
+/// Walks from the chunk `start` the camera is in through open space and returns
+/// every chunk it reaches.
+///
+/// A chunk entered through one face is only left through the faces
+/// `connections` joins to it, and only away from `start`, so the walk never
+/// turns back on itself. The walk leaves out chunks `in_view` rejects and
+/// chunks `connections` returns `None` for, such as ones past the edge of the
+/// world.
+pub fn visible_chunks(
+    start: IVec3,
+    connections: impl Fn(IVec3) -> Option<FaceConnections>,
+    in_view: impl Fn(IVec3) -> bool,
+) -> HashSet<IVec3> {
+    let Some(open) = connections(start) else {
+        return HashSet::new();
+    };
+    let mut visible = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, None, open)]);
+    while let Some((coord, entered, open)) = queue.pop_front() {
+        for side in BlockSides::ALL {
+            let step = side.normal().as_ivec3();
+            let next = coord + step;
+            let away = (next - start).dot(step) > 0;
+            let through = entered.is_none_or(|face| open.connects(face, side));
+            if !away || !through || visible.contains(&next) || !in_view(next) {
+                continue;
+            }
+            if let Some(next_open) = connections(next) {
+                visible.insert(next);
+                queue.push_back((next, Some(opposite(side)), next_open));
+            }
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::{blocks::BlockType, registry::test_registry};
+
+    const STONE: BlockType = BlockType(1);
+
+    fn chunk(solid: impl Fn(i32, i32, i32) -> bool) -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.fill_where(STONE, solid);
+        chunk
+    }
+
+    fn connections(solid: impl Fn(i32, i32, i32) -> bool) -> FaceConnections {
+        FaceConnections::compute(&chunk(solid), &test_registry())
+    }
+
+    /// Every pair of different faces that connects.
+    fn pairs(connections: FaceConnections) -> Vec<(BlockSides, BlockSides)> {
+        let mut pairs = Vec::new();
+        for (i, a) in BlockSides::ALL.into_iter().enumerate() {
+            for b in BlockSides::ALL.into_iter().skip(i + 1) {
+                if connections.connects(a, b) {
+                    assert!(connections.connects(b, a));
+                    pairs.push((a, b));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn empty_and_solid_chunks() {
+        assert_eq!(connections(|_, _, _| false), FaceConnections::ALL);
+        assert_eq!(connections(|_, _, _| true), FaceConnections::NONE);
+        // Air sealed inside the rock reaches no face.
+        assert_eq!(
+            connections(|x, y, z| !(4..12).contains(&x)
+                || !(4..12).contains(&y)
+                || !(4..12).contains(&z)),
+            FaceConnections::NONE
+        );
+    }
+
+    #[test]
+    fn a_tunnel_joins_its_two_ends() {
+        // Along x, through the middle of solid rock.
+        let tunnel = connections(|_, y, z| !(y == 8 && z == 8));
+        assert_eq!(pairs(tunnel), [(BlockSides::Left, BlockSides::Right)]);
+        // Bending upwards at x 5.
+        let bend =
+            connections(|x, y, z| !((y == 8 && z == 8 && x <= 5) || (x == 5 && z == 8 && y >= 8)));
+        assert_eq!(pairs(bend), [(BlockSides::Top, BlockSides::Right)]);
+    }
+
+    #[test]
+    fn a_floor_splits_top_from_bottom() {
+        let floor = connections(|_, y, _| y == 8);
+        assert!(!floor.connects(BlockSides::Top, BlockSides::Bottom));
+        for side in [
+            BlockSides::Front,
+            BlockSides::Back,
+            BlockSides::Left,
+            BlockSides::Right,
+        ] {
+            assert!(floor.connects(BlockSides::Top, side));
+            assert!(floor.connects(BlockSides::Bottom, side));
+        }
+        // A single hole joins them again.
+        let holed = connections(|x, y, z| y == 8 && !(x == 3 && z == 12));
+        assert!(holed.connects(BlockSides::Top, BlockSides::Bottom));
+    }
+
+    #[test]
+    fn two_caves_stay_apart() {
+        let caves = connections(|x, y, z| {
+            let first = y == 2 && z == 2;
+            let second = x == 12 && z == 12;
+            !(first || second)
+        });
+        assert_eq!(
+            pairs(caves),
+            [
+                (BlockSides::Top, BlockSides::Bottom),
+                (BlockSides::Left, BlockSides::Right)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_walk_stops_at_sealed_chunks() {
+        // A row of chunks along x: open, open, solid, open, open.
+        let row = |coord: IVec3| match coord {
+            c if c.y != 0 || c.z != 0 || !(0..5).contains(&c.x) => None,
+            c if c.x == 2 => Some(FaceConnections::NONE),
+            _ => Some(FaceConnections::ALL),
+        };
+        let visible = visible_chunks(IVec3::ZERO, row, |_| true);
+        let mut coords: Vec<IVec3> = visible.into_iter().collect();
+        coords.sort_by_key(|coord| coord.to_array());
+        // The solid chunk itself can be seen, but nothing behind it.
+        assert_eq!(coords, [IVec3::ZERO, IVec3::X, ivec3(2, 0, 0)]);
+    }
+
+    #[test]
+    fn the_walk_follows_tunnels() {
+        let tunnel = connections(|_, y, z| !(y == 8 && z == 8));
+        // A 5x5x5 block of rock with a tunnel along x through its middle row.
+        let rock = |coord: IVec3| {
+            if !coord.cmpge(IVec3::ZERO).all() || !coord.cmplt(IVec3::splat(5)).all() {
+                None
+            } else if coord.y == 2 && coord.z == 2 {
+                Some(tunnel)
+            } else {
+                Some(FaceConnections::NONE)
+            }
+        };
+        let visible = visible_chunks(ivec3(0, 2, 2), rock, |_| true);
+        for x in 0..5 {
+            assert!(visible.contains(&ivec3(x, 2, 2)));
+        }
+        // The rock right around the camera, but nothing the tunnel does not
+        // lead to.
+        assert!(visible.contains(&ivec3(0, 3, 2)));
+        assert!(!visible.contains(&ivec3(1, 3, 2)));
+        assert!(!visible.contains(&ivec3(4, 2, 0)));
+
+        // The frustum still applies.
+        let ahead = visible_chunks(ivec3(0, 2, 2), rock, |coord| coord.x < 3);
+        assert!(!ahead.contains(&ivec3(3, 2, 2)));
+    }
+}
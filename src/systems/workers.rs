@@ -0,0 +1,321 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::systems::{
+    blocks::BlockMesh,
+    chunks::Chunk,
+    meshing::{MeshInput, MeshingMode},
+    registry::BlockRegistry,
+    structures::{BlockWrite, StructureBlocks},
+    terrain::TerrainGenerator,
+    visibility::FaceConnections,
+};
+use macroquad::prelude::*;
+
+/// Worker threads to start on this machine: one per core, minus the one the
+/// render loop runs on.
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|cores| cores.get().saturating_sub(1))
+        .unwrap_or(1)
+        .max(1)
+}
+
+enum Job {
+    Generate(IVec3),
+    Mesh {
+        id: u64,
+        input: MeshInput,
+        mode: MeshingMode,
+    },
+}
+
+/// A job a worker has finished.
+pub enum Finished {
+    /// A generated chunk and the blocks of the structures starting in it, still
+    /// to be inserted with `StructurePlacer::place`.
+    Generated {
+        chunk: Chunk,
+        writes: Vec<BlockWrite>,
+    },
+    /// CPU side meshes for the chunk at `coord` and the connections between its
+    /// faces, still to be handed to it.
+    Meshed {
+        coord: IVec3,
+        meshes: Vec<BlockMesh>,
+        connections: FaceConnections,
+    },
+}
+
+/// What every worker reads, shared between them.
+struct Shared {
+    generator: Arc<TerrainGenerator>,
+    structures: StructureBlocks,
+    registry: Arc<BlockRegistry>,
+}
+
+impl Shared {
+    fn run(&self, job: Job) -> (u64, Finished) {
+        match job {
+            Job::Generate(coord) => (
+                0,
+                Finished::Generated {
+                    chunk: self.generator.generate(coord),
+                    writes: self.structures.structures(&self.generator, coord),
+                },
+            ),
+            Job::Mesh { id, input, mode } => (
+                id,
+                Finished::Meshed {
+                    coord: input.coord(),
+                    meshes: input.build(mode, &self.registry),
+                    connections: input.connections(&self.registry),
+                },
+            ),
+        }
+    }
+}
+
+/// A pool of threads that generate and mesh chunks off the render thread.
+///
+/// Jobs go out and results come back over channels. Only the newest mesh job of
+/// each chunk is delivered: a chunk edited while it was being meshed is queued
+/// again, and the outdated result is dropped when it arrives.
+pub struct ChunkWorkers {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<(u64, Finished)>,
+    threads: Vec<JoinHandle<()>>,
+    generating: HashSet<IVec3>,
+    /// Id of the newest mesh job of every chunk with one in flight.
+    meshing: HashMap<IVec3, u64>,
+    next_id: u64,
+}
+
+impl ChunkWorkers {
+    pub fn new(
+        threads: usize,
+        generator: Arc<TerrainGenerator>,
+        structures: StructureBlocks,
+        registry: Arc<BlockRegistry>,
+    ) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let shared = Arc::new(Shared {
+            generator,
+            structures,
+            registry,
+        });
+
+        let threads = (0..threads)
+            .map(|_| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || loop {
+                    // The lock is released as soon as a job is taken.
+                    let job = jobs.lock().unwrap().recv();
+                    let Ok(job) = job else {
+                        break;
+                    };
+                    if results.send(shared.run(job)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            threads,
+            generating: HashSet::new(),
+            meshing: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    fn send(&self, job: Job) {
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .expect("chunk worker threads have stopped");
+    }
+
+    /// Queues generating the chunk at `coord`, unless it is already queued.
+    pub fn generate(&mut self, coord: IVec3) {
+        if self.generating.insert(coord) {
+            self.send(Job::Generate(coord));
+        }
+    }
+
+    /// Queues meshing the chunk copied into `input`. Any mesh job still in
+    /// flight for the same chunk is superseded.
+    pub fn mesh(&mut self, input: MeshInput, mode: MeshingMode) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.meshing.insert(input.coord(), id);
+        self.send(Job::Mesh { id, input, mode });
+    }
+
+    /// Jobs queued or running.
+    pub fn in_flight(&self) -> usize {
+        self.generating.len() + self.meshing.len()
+    }
+
+    /// Takes the next finished job, if there is one, without waiting.
+    pub fn poll(&mut self) -> Option<Finished> {
+        loop {
+            let (id, finished) = self.results.try_recv().ok()?;
+            if let Some(finished) = self.accept(id, finished) {
+                return Some(finished);
+            }
+        }
+    }
+
+    /// Drops outdated mesh results and stops tracking finished jobs.
+    fn accept(&mut self, id: u64, finished: Finished) -> Option<Finished> {
+        match &finished {
+            Finished::Generated { chunk, .. } => {
+                self.generating.remove(&chunk.position());
+            }
+            Finished::Meshed { coord, .. } => {
+                if self.meshing.get(coord) != Some(&id) {
+                    return None;
+                }
+                self.meshing.remove(coord);
+            }
+        }
+        Some(finished)
+    }
+}
+
+impl Drop for ChunkWorkers {
+    /// Closes the job channel so every worker leaves its loop, then waits for
+    /// them to finish the job at hand.
+    fn drop(&mut self) {
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        systems::{
+            blocks::BlockType, registry::test_registry, terrain::TerrainBlocks, world::World,
+        },
+        CHUNK_SIZE_16,
+    };
+
+    fn workers(threads: usize) -> (ChunkWorkers, Arc<TerrainGenerator>, StructureBlocks) {
+        let registry = Arc::new(test_registry());
+        let generator = Arc::new(TerrainGenerator::new(
+            4,
+            TerrainBlocks::from_registry(&registry).unwrap(),
+        ));
+        let structures = StructureBlocks::from_registry(&registry).unwrap();
+        let workers = ChunkWorkers::new(threads, Arc::clone(&generator), structures, registry);
+        (workers, generator, structures)
+    }
+
+    /// Waits for the next delivered result.
+    fn wait(workers: &mut ChunkWorkers) -> Option<Finished> {
+        loop {
+            let (id, finished) = workers.results.recv_timeout(Duration::from_secs(10)).ok()?;
+            if let Some(finished) = workers.accept(id, finished) {
+                return Some(finished);
+            }
+        }
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<BlockType> {
+        (0..CHUNK_SIZE_16.pow(3))
+            .map(|i| chunk.get(i % 16, i / 256, i / 16 % 16))
+            .collect()
+    }
+
+    #[test]
+    fn generates_the_same_chunks_as_the_generator() {
+        let (mut workers, generator, structures) = workers(3);
+        let coords = [
+            ivec3(0, 2, 0),
+            ivec3(-1, 2, 0),
+            ivec3(4, 1, -3),
+            ivec3(0, 0, 0),
+        ];
+        for coord in coords {
+            workers.generate(coord);
+        }
+        // Already queued, so not generated twice.
+        workers.generate(coords[0]);
+        assert_eq!(workers.in_flight(), coords.len());
+
+        let mut seen = Vec::new();
+        for _ in coords {
+            let Some(Finished::Generated { chunk, writes }) = wait(&mut workers) else {
+                panic!("expected a generated chunk");
+            };
+            let coord = chunk.position();
+            assert_eq!(
+                blocks(&chunk),
+                blocks(&generator.generate(coord)),
+                "{coord}"
+            );
+            assert_eq!(writes, structures.structures(&generator, coord));
+            seen.push(coord);
+        }
+        seen.sort_by_key(|coord| coord.to_array());
+        let mut expected = coords.to_vec();
+        expected.sort_by_key(|coord| coord.to_array());
+        assert_eq!(seen, expected);
+        assert_eq!(workers.in_flight(), 0);
+        assert!(workers.poll().is_none());
+    }
+
+    #[test]
+    fn only_the_newest_mesh_is_delivered() {
+        let (mut workers, generator, _) = workers(2);
+        let registry = test_registry();
+        let mut world = World::new();
+        world.insert(generator.generate(ivec3(0, 2, 0)));
+
+        let first = world.take_dirty(1, IVec3::ZERO, &registry).remove(0);
+        workers.mesh(first, MeshingMode::Naive);
+        world.set_block(ivec3(3, 40, 3), BlockType(1));
+        let second = world.take_dirty(1, IVec3::ZERO, &registry).remove(0);
+        let expected = second.build(MeshingMode::Greedy, &registry);
+        workers.mesh(second, MeshingMode::Greedy);
+
+        let Some(Finished::Meshed {
+            coord,
+            meshes,
+            connections,
+        }) = wait(&mut workers)
+        else {
+            panic!("expected a mesh");
+        };
+        assert_eq!(coord, ivec3(0, 2, 0));
+        assert_eq!(format!("{meshes:?}"), format!("{expected:?}"));
+        assert_eq!(
+            connections,
+            FaceConnections::compute(&world.chunk(coord).unwrap().snapshot(), &registry)
+        );
+        assert_eq!(workers.in_flight(), 0);
+        // The first job finishes too, but is never handed out.
+        thread::sleep(Duration::from_millis(200));
+        assert!(workers.poll().is_none());
+    }
+}
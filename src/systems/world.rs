@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+
+use crate::{
+    systems::{
+        blocks::{BlockMesh, BlockType},
+        chunks::{chunk_bounds, proper_mod, Chunk},
+        frustum::Frustum,
+        lod::LodSettings,
+        meshing::{MeshInput, MeshingMode},
+        registry::BlockRegistry,
+        visibility::{visible_chunks, FaceConnections},
+    },
+    CHUNK_SIZE_16,
+};
+use macroquad::prelude::*;
+
+/// Splits a world block position into the coordinate of the chunk that holds it
+/// and the block's local position inside that chunk.
+pub fn world_to_chunk(pos: IVec3) -> (IVec3, IVec3) {
+    let local = ivec3(
+        proper_mod(pos.x, CHUNK_SIZE_16),
+        proper_mod(pos.y, CHUNK_SIZE_16),
+        proper_mod(pos.z, CHUNK_SIZE_16),
+    );
+    ((pos - local) / CHUNK_SIZE_16, local)
+}
+
+/// How many chunks the last `World::render` drew, and why it skipped the others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub drawn: usize,
+    /// Outside the frustum.
+    pub culled: usize,
+    /// In the frustum, but hidden behind solid chunks.
+    pub occluded: usize,
+}
+
+/// All loaded chunks, keyed by chunk coordinate.
+pub struct World {
+    chunks: HashMap<IVec3, Chunk>,
+    meshing_mode: MeshingMode,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            meshing_mode: MeshingMode::Naive,
+        }
+    }
+
+    /// Switching modes marks every chunk dirty so it is remeshed once
+    /// `take_dirty` hands it out again.
+    pub fn set_meshing_mode(&mut self, mode: MeshingMode) {
+        if self.meshing_mode == mode {
+            return;
+        }
+        self.meshing_mode = mode;
+        for chunk in self.chunks.values_mut() {
+            chunk.mark_dirty();
+        }
+    }
+
+    pub fn chunk(&self, coord: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns the chunk at `coord`, creating an empty one if it is not loaded.
+    pub fn chunk_or_insert(&mut self, coord: IVec3) -> &mut Chunk {
+        self.chunks
+            .entry(coord)
+            .or_insert_with(|| Chunk::new(coord))
+    }
+
+    /// Coordinates of every loaded chunk, in no particular order.
+    pub fn coords(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Adds a fully built chunk, replacing any chunk at the same coordinate. Every
+    /// loaded chunk touching it is marked dirty, since its border faces and
+    /// ambient occlusion may change.
+    pub fn insert(&mut self, chunk: Chunk) {
+        let coord = chunk.position();
+        self.mark_neighbours_dirty(coord);
+        self.chunks.insert(coord, chunk);
+    }
+
+    /// Unloads the chunk at `coord`. The chunks around it are marked dirty, since
+    /// their faces towards it are no longer covered.
+    pub fn remove(&mut self, coord: IVec3) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&coord)?;
+        self.mark_neighbours_dirty(coord);
+        Some(chunk)
+    }
+
+    fn mark_neighbours_dirty(&mut self, coord: IVec3) {
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if let Some(neighbour) = self.chunks.get_mut(&(coord + ivec3(x, y, z))) {
+                        neighbour.mark_dirty();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blocks in chunks that are not loaded read as air.
+    pub fn get_block(&self, pos: IVec3) -> BlockType {
+        let (coord, local) = world_to_chunk(pos);
+        match self.chunks.get(&coord) {
+            Some(chunk) => chunk.get(local.x, local.y, local.z),
+            None => BlockType::AIR,
+        }
+    }
+
+    /// Changing a block on a chunk border also marks the chunks across that border
+    /// dirty, since their faces may have been covered or uncovered. Ambient
+    /// occlusion reaches diagonally, so chunks that only share an edge or a
+    /// corner with the block count too.
+    pub fn set_block(&mut self, pos: IVec3, block_type: BlockType) {
+        let (coord, local) = world_to_chunk(pos);
+        let chunk = self.chunk_or_insert(coord);
+        if chunk.get(local.x, local.y, local.z) == block_type {
+            return;
+        }
+        chunk.set(local.x, local.y, local.z, block_type);
+
+        let steps = |axis: usize| {
+            if local[axis] == 0 {
+                -1..=0
+            } else if local[axis] == CHUNK_SIZE_16 - 1 {
+                0..=1
+            } else {
+                0..=0
+            }
+        };
+        for x in steps(0) {
+            for y in steps(1) {
+                for z in steps(2) {
+                    let step = ivec3(x, y, z);
+                    if step == IVec3::ZERO {
+                        continue;
+                    }
+                    if let Some(neighbour) = self.chunks.get_mut(&(coord + step)) {
+                        neighbour.mark_dirty();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Picks the level of detail of every chunk from its distance to the chunk
+    /// `center`. Chunks that change level are marked for remeshing.
+    pub fn update_lod(&mut self, center: IVec3, settings: &LodSettings) {
+        for (&coord, chunk) in &mut self.chunks {
+            chunk.set_lod(settings.level(coord - center));
+        }
+    }
+
+    pub fn meshing_mode(&self) -> MeshingMode {
+        self.meshing_mode
+    }
+
+    /// Copies out up to `limit` dirty chunks for meshing, nearest to the chunk
+    /// `near` first, and clears their dirty flags. Faces are culled against the
+    /// neighbouring chunks as they are now.
+    pub fn take_dirty(
+        &mut self,
+        limit: usize,
+        near: IVec3,
+        registry: &BlockRegistry,
+    ) -> Vec<MeshInput> {
+        let mut dirty: Vec<IVec3> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.is_dirty())
+            .map(|(coord, _)| *coord)
+            .collect();
+        dirty.sort_by_key(|&coord| (coord - near).length_squared());
+        dirty.truncate(limit);
+
+        let inputs = dirty
+            .iter()
+            .map(|coord| {
+                MeshInput::new(&self.chunks[coord], |pos| {
+                    registry.is_transparent(self.get_block(pos))
+                })
+            })
+            .collect();
+        for coord in dirty {
+            self.chunks.get_mut(&coord).unwrap().clear_dirty();
+        }
+        inputs
+    }
+
+    /// Hands finished meshes to the chunk at `coord`, if it is still loaded.
+    pub fn set_meshes(&mut self, coord: IVec3, meshes: Vec<BlockMesh>, texture: &Texture2D) {
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.set_meshes(meshes, texture);
+        }
+    }
+
+    /// Records which faces of the chunk at `coord` see each other, if it is
+    /// still loaded.
+    pub fn set_connections(&mut self, coord: IVec3, connections: FaceConnections) {
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.set_connections(connections);
+        }
+    }
+
+    /// Draws the chunks inside `frustum` that can be seen from `camera` through
+    /// open space, and skips the rest.
+    pub fn render(&self, frustum: &Frustum, camera: Vec3) -> RenderStats {
+        let start = world_to_chunk(camera.floor().as_ivec3()).0;
+        // Chunks that are not loaded are walked through as open space, within
+        // the box around the loaded ones and the camera.
+        let (min, max) = self
+            .chunks
+            .keys()
+            .fold((start, start), |(min, max), &coord| {
+                (min.min(coord), max.max(coord))
+            });
+        let in_view = |coord| {
+            let (min, max) = chunk_bounds(coord);
+            frustum.intersects_aabb(min, max)
+        };
+        let visible = visible_chunks(
+            start,
+            |coord| match self.chunks.get(&coord) {
+                Some(chunk) => Some(chunk.connections()),
+                None if coord.cmpge(min).all() && coord.cmple(max).all() => {
+                    Some(FaceConnections::ALL)
+                }
+                None => None,
+            },
+            in_view,
+        );
+
+        let mut stats = RenderStats::default();
+        for (&coord, chunk) in &self.chunks {
+            if visible.contains(&coord) {
+                chunk.render();
+                stats.drawn += 1;
+            } else if in_view(coord) {
+                stats.occluded += 1;
+            } else {
+                stats.culled += 1;
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::registry::test_registry;
+
+    const STONE: BlockType = BlockType(1);
+    const GRASS: BlockType = BlockType(3);
+
+    #[test]
+    fn world_to_chunk_handles_negatives() {
+        assert_eq!(world_to_chunk(ivec3(0, 0, 0)), (IVec3::ZERO, IVec3::ZERO));
+        assert_eq!(
+            world_to_chunk(ivec3(15, 16, 17)),
+            (ivec3(0, 1, 1), ivec3(15, 0, 1))
+        );
+        assert_eq!(
+            world_to_chunk(ivec3(-1, -16, -17)),
+            (ivec3(-1, -1, -2), ivec3(15, 0, 15))
+        );
+        assert_eq!(
+            world_to_chunk(ivec3(-32, -33, 31)),
+            (ivec3(-2, -3, 1), ivec3(0, 15, 15))
+        );
+    }
+
+    #[test]
+    fn set_block_creates_chunks_lazily() {
+        let mut world = World::new();
+        assert_eq!(world.get_block(ivec3(-1, 5, 20)), BlockType::AIR);
+        assert_eq!(world.chunk_count(), 0);
+
+        world.set_block(ivec3(-1, 5, 20), STONE);
+        assert_eq!(world.chunk_count(), 1);
+        assert_eq!(world.get_block(ivec3(-1, 5, 20)), STONE);
+
+        let chunk = world.chunk(ivec3(-1, 0, 1)).unwrap();
+        assert_eq!(chunk.position(), ivec3(-1, 0, 1));
+        assert_eq!(chunk.origin(), ivec3(-16, 0, 16));
+        assert_eq!(chunk.get(15, 5, 4), STONE);
+
+        world.set_block(ivec3(-2, 5, 20), GRASS);
+        assert_eq!(world.chunk_count(), 1);
+        world.set_block(ivec3(0, 5, 20), GRASS);
+        assert_eq!(world.chunk_count(), 2);
+    }
+
+    #[test]
+    fn border_edits_dirty_the_neighbour() {
+        let mut world = World::new();
+        world.set_block(ivec3(0, 0, 0), STONE);
+        world.set_block(ivec3(-1, 0, 0), STONE);
+        world.set_block(ivec3(8, 0, 0), STONE);
+        for coord in [ivec3(0, 0, 0), ivec3(-1, 0, 0)] {
+            world.chunks.get_mut(&coord).unwrap().clear_dirty();
+        }
+
+        world.set_block(ivec3(8, 1, 8), STONE);
+        assert!(world.chunk(ivec3(0, 0, 0)).unwrap().is_dirty());
+        assert!(!world.chunk(ivec3(-1, 0, 0)).unwrap().is_dirty());
+
+        world.chunks.get_mut(&IVec3::ZERO).unwrap().clear_dirty();
+        world.set_block(ivec3(0, 0, 0), BlockType::AIR);
+        assert!(world.chunk(ivec3(0, 0, 0)).unwrap().is_dirty());
+        assert!(world.chunk(ivec3(-1, 0, 0)).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn corner_edits_dirty_diagonal_neighbours() {
+        let mut world = World::new();
+        for coord in [
+            ivec3(0, 0, 0),
+            ivec3(1, 0, 1),
+            ivec3(1, 1, 1),
+            ivec3(0, 0, 2),
+        ] {
+            world.chunk_or_insert(coord).clear_dirty();
+        }
+
+        world.set_block(ivec3(15, 15, 15), STONE);
+        assert!(world.chunk(ivec3(1, 0, 1)).unwrap().is_dirty());
+        assert!(world.chunk(ivec3(1, 1, 1)).unwrap().is_dirty());
+        assert!(!world.chunk(ivec3(0, 0, 2)).unwrap().is_dirty());
+        assert_eq!(world.chunk_count(), 4);
+    }
+
+    #[test]
+    fn insert_dirties_every_touching_chunk() {
+        let mut world = World::new();
+        for coord in [ivec3(1, 1, 1), ivec3(2, 0, 0), ivec3(0, 0, 0)] {
+            world.chunk_or_insert(coord).clear_dirty();
+        }
+        world.insert(Chunk::new(IVec3::ZERO));
+        assert_eq!(world.chunk_count(), 3);
+        assert!(world.chunk(IVec3::ZERO).unwrap().is_dirty());
+        assert!(world.chunk(ivec3(1, 1, 1)).unwrap().is_dirty());
+        assert!(!world.chunk(ivec3(2, 0, 0)).unwrap().is_dirty());
+    }
+
+    #[test]
+    fn remove_unloads_and_dirties_neighbours() {
+        let mut world = World::new();
+        for coord in [ivec3(0, 0, 0), ivec3(-1, 1, 0), ivec3(3, 0, 0)] {
+            world.chunk_or_insert(coord).clear_dirty();
+        }
+        assert_eq!(world.remove(IVec3::ZERO).unwrap().position(), IVec3::ZERO);
+        assert!(world.remove(IVec3::ZERO).is_none());
+        assert!(world.chunk(ivec3(-1, 1, 0)).unwrap().is_dirty());
+        assert!(!world.chunk(ivec3(3, 0, 0)).unwrap().is_dirty());
+
+        let mut coords: Vec<IVec3> = world.coords().collect();
+        coords.sort_by_key(|coord| coord.x);
+        assert_eq!(coords, [ivec3(-1, 1, 0), ivec3(3, 0, 0)]);
+    }
+
+    #[test]
+    fn border_faces_are_culled_against_loaded_neighbours() {
+        let mut world = World::new();
+        world.set_block(ivec3(15, 0, 0), STONE);
+        world.set_block(ivec3(16, 0, 0), STONE);
+
+        let registry = test_registry();
+        let is_air = |pos: IVec3| registry.is_transparent(world.get_block(pos));
+        let west = world.chunk(ivec3(0, 0, 0)).unwrap();
+        let east = world.chunk(ivec3(1, 0, 0)).unwrap();
+        assert!(west
+            .visible_sides(15, 0, 0, &registry, &is_air)
+            .left
+            .is_none());
+        assert!(east
+            .visible_sides(0, 0, 0, &registry, &is_air)
+            .right
+            .is_none());
+        assert!(east
+            .visible_sides(0, 0, 0, &registry, &is_air)
+            .left
+            .is_some());
+    }
+
+    #[test]
+    fn take_dirty_hands_out_nearest_chunks_first() {
+        let registry = test_registry();
+        let mut world = World::new();
+        for x in [5, -1, 2, 0] {
+            world.set_block(ivec3(x * 16, 0, 0), STONE);
+        }
+        // Covers the face of the block at x = 0 that faces the chunk at x = -1.
+        world.set_block(ivec3(-1, 0, 0), STONE);
+
+        let inputs = world.take_dirty(2, IVec3::ZERO, &registry);
+        let coords: Vec<IVec3> = inputs.iter().map(|input| input.coord()).collect();
+        assert_eq!(coords, [IVec3::ZERO, ivec3(-1, 0, 0)]);
+        assert!(!world.chunk(IVec3::ZERO).unwrap().is_dirty());
+        assert!(world.chunk(ivec3(2, 0, 0)).unwrap().is_dirty());
+
+        let meshes = inputs[0].build(MeshingMode::Naive, &registry);
+        let faces = meshes.iter().map(|mesh| mesh.indices.len()).sum::<usize>() / 6;
+        assert_eq!(faces, 5);
+
+        let rest = world.take_dirty(10, IVec3::ZERO, &registry);
+        assert_eq!(rest.len(), 2);
+        assert!(world.take_dirty(10, IVec3::ZERO, &registry).is_empty());
+    }
+
+    #[test]
+    fn render_culls_chunks_outside_the_frustum() {
+        let mut world = World::new();
+        for x in -2..=2 {
+            world.insert(Chunk::new(ivec3(x, 0, 0)));
+        }
+        // Standing in chunk (0, 0, 0), looking down +x.
+        let frustum = Frustum::from_camera(&Camera3D {
+            position: vec3(8.0, 8.0, 8.0),
+            target: vec3(9.0, 8.0, 8.0),
+            up: Vec3::Y,
+            aspect: Some(1.0),
+            fovy: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            world.render(&frustum, vec3(8.0, 8.0, 8.0)),
+            RenderStats {
+                drawn: 3,
+                culled: 2,
+                occluded: 0
+            }
+        );
+    }
+
+    #[test]
+    fn render_skips_chunks_sealed_off_by_rock() {
+        let mut world = World::new();
+        for x in -1..=4 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    world.insert(Chunk::new(ivec3(x, y, z)));
+                }
+            }
+        }
+        // A wall of solid chunks at x 2.
+        for y in -1..=1 {
+            for z in -1..=1 {
+                world.set_connections(ivec3(2, y, z), FaceConnections::NONE);
+            }
+        }
+        let frustum = Frustum::from_camera(&Camera3D {
+            position: vec3(8.0, 8.0, 8.0),
+            target: vec3(9.0, 8.0, 8.0),
+            up: Vec3::Y,
+            aspect: Some(1.0),
+            fovy: 1.5,
+            ..Default::default()
+        });
+        let sealed = world.render(&frustum, vec3(8.0, 8.0, 8.0));
+        assert_eq!(sealed.occluded, 9 * 2);
+
+        let mut tunnel = Chunk::new(IVec3::ZERO);
+        tunnel.fill_where(STONE, |_, y, z| !(y == 8 && z == 8));
+        let registry = test_registry();
+        world.set_connections(ivec3(2, 0, 0), FaceConnections::compute(&tunnel, &registry));
+        let open = world.render(&frustum, vec3(8.0, 8.0, 8.0));
+        // The tunnel opens onto empty chunks, so everything behind the wall
+        // shows.
+        assert_eq!(open.drawn, sealed.drawn + sealed.occluded);
+        assert_eq!(open.occluded, 0);
+    }
+
+    #[test]
+    fn lod_changes_dirty_only_the_chunks_they_touch() {
+        let mut world = World::new();
+        for x in [0, 3, 6] {
+            world.insert(Chunk::new(ivec3(x, 0, 0)));
+            world.chunks.get_mut(&ivec3(x, 0, 0)).unwrap().clear_dirty();
+        }
+        let settings = LodSettings::spaced(4);
+        world.update_lod(IVec3::ZERO, &settings);
+        let state = |world: &World, x| {
+            let chunk = world.chunk(ivec3(x, 0, 0)).unwrap();
+            (chunk.lod(), chunk.is_dirty())
+        };
+        assert_eq!(state(&world, 0), (0, false));
+        assert_eq!(state(&world, 3), (0, false));
+        assert_eq!(state(&world, 6), (1, true));
+
+        // Walking to chunk 10 turns the first chunk coarse.
+        world.update_lod(ivec3(10, 0, 0), &settings);
+        assert_eq!(state(&world, 0), (2, true));
+        assert_eq!(state(&world, 3), (1, true));
+        assert_eq!(state(&world, 6), (0, true));
+    }
+}